@@ -0,0 +1,9 @@
+-- Table to keep addresses and whole domains that must never be mailed.
+CREATE TABLE suppressions(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    kind TEXT NOT NULL CHECK (kind IN ('address', 'domain')),
+    value TEXT NOT NULL UNIQUE,
+    reason TEXT NULL,
+    created_at timestamptz NOT NULL
+);
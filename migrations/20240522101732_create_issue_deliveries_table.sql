@@ -0,0 +1,11 @@
+-- Table to keep track of the delivery of an issue to each of its recipients.
+CREATE TABLE issue_deliveries(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('queued', 'sent', 'failed', 'skipped')),
+    provider_message_id TEXT NULL,
+    failure_reason TEXT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,30 @@
+-- Make the uniqueness of the emails of the subscribers ignore the case of their
+-- domain. We wrap the whole migration in a transaction to make sure
+-- it succeeds or fails atomically.
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN normalized_email TEXT NULL;
+    -- Backfill the normalized email for historical entries, following the rule of
+    -- the application: the local part is kept as it is (RFC 5321), and the domain
+    -- is lowercased. The domains of existing entries were never IDNA-encoded, run
+    -- `newsletter-admin normalize-emails` to encode them.
+    UPDATE subscriptions
+        SET normalized_email =
+            split_part(email, '@', 1) || '@' || lower(split_part(email, '@', 2));
+
+    -- No subscriber is deleted here: subscribers that only differ in the case of
+    -- their domain have to be merged by hand before running this migration.
+    DO $$
+    BEGIN
+        IF EXISTS (
+            SELECT normalized_email FROM subscriptions
+            GROUP BY normalized_email HAVING count(*) > 1
+        ) THEN
+            RAISE EXCEPTION 'Some subscribers only differ in the case of the domain of their email, merge them before migrating.';
+        END IF;
+    END
+    $$;
+
+    ALTER TABLE subscriptions ALTER COLUMN normalized_email SET NOT NULL;
+    CREATE UNIQUE INDEX subscriptions_normalized_email_key
+        ON subscriptions (normalized_email);
+COMMIT;
@@ -0,0 +1,2 @@
+-- Language in which emails are sent to each subscriber.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
@@ -0,0 +1,10 @@
+-- Translations of newsletter issues, one per locale.
+CREATE TABLE newsletter_issue_variants(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    locale TEXT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, locale)
+);
@@ -0,0 +1,8 @@
+-- Tokens that let subscribers export or erase their data.
+CREATE TABLE privacy_tokens(
+    privacy_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (privacy_token)
+);
@@ -0,0 +1,27 @@
+-- Append-only audit log of the consent given or withdrawn by subscribers.
+CREATE TABLE consent_events(
+    consent_event_id uuid NOT NULL,
+    PRIMARY KEY (consent_event_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL
+        CHECK (kind IN ('subscribe', 'confirm', 'unsubscribe', 'preferences_change')),
+    source TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id);
+
+-- Events can't be modified once they are recorded. They are only deleted along
+-- with their subscriber, when the subscriber asks for their data to be erased.
+CREATE FUNCTION reject_consent_event_updates() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent events are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_are_append_only
+    BEFORE UPDATE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_updates();
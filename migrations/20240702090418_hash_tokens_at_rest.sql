@@ -0,0 +1,13 @@
+-- Store the SHA-256 hash of the tokens instead of the tokens themselves.
+-- Existing tokens are hashed in place, so the links that were already emailed to
+-- subscribers keep working.
+ALTER TABLE subscription_tokens
+    RENAME COLUMN subscription_token TO subscription_token_hash;
+UPDATE subscription_tokens
+    SET subscription_token_hash =
+        encode(sha256(convert_to(subscription_token_hash, 'UTF8')), 'hex');
+
+ALTER TABLE privacy_tokens
+    RENAME COLUMN privacy_token TO privacy_token_hash;
+UPDATE privacy_tokens
+    SET privacy_token_hash = encode(sha256(convert_to(privacy_token_hash, 'UTF8')), 'hex');
@@ -0,0 +1,7 @@
+-- Table of the administrators of the newsletter.
+CREATE TABLE users(
+    user_id uuid PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,17 @@
+-- Consent events can't be deleted either, unless their subscriber was deleted: the
+-- events of a subscriber are deleted, by the cascade of the foreign key, when the
+-- subscriber asks for their data to be erased.
+CREATE OR REPLACE FUNCTION reject_consent_event_updates() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE'
+        AND NOT EXISTS (SELECT 1 FROM subscriptions WHERE id = OLD.subscriber_id) THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'consent events are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER consent_events_are_append_only ON consent_events;
+CREATE TRIGGER consent_events_are_append_only
+    BEFORE UPDATE OR DELETE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_updates();
@@ -0,0 +1,352 @@
+//! Module that includes the operations of the `newsletter-admin` binary.
+//!
+//! # Description
+//!
+//! Each operation is a plain function that takes the connection pool (or the email
+//! client), so the binary only has to parse the command line and print the results.
+//! The operations reuse the same queries as the API wherever possible, so changes
+//! made through the CLI look the same as the ones made through the API, e.g.
+//! confirming a subscriber records a consent event, and deleting a subscriber
+//! anonymizes their delivery records.
+
+use crate::consent::{record_consent_event, ConsentContext, ConsentEventKind};
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use crate::routes::erase_subscriber;
+use crate::telemetry::pii;
+use crate::EmailClient;
+use anyhow::Context;
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Argon2, PasswordHasher};
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Source recorded in the consent events of the changes made through the CLI.
+const CONSENT_SOURCE: &str = "admin_cli";
+
+/// A subscriber, as listed by the CLI.
+#[derive(Debug)]
+pub struct SubscriberSummary {
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub locale: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// Outcome of normalizing the stored emails again.
+#[derive(Debug, Default)]
+pub struct NormalizationReport {
+    /// Number of subscribers and suppressed addresses whose normalized form changed.
+    pub updated: u64,
+    /// Emails of the subscribers that were left unchanged, because their new
+    /// normalized form belongs to another subscriber.
+    pub conflicts: Vec<String>,
+}
+
+/// Create an administrator, storing the Argon2 hash of their password.
+#[tracing::instrument(name = "Create an admin user", skip(pool, password))]
+pub async fn create_admin_user(
+    pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+) -> Result<Uuid, anyhow::Error> {
+    if username.trim().is_empty() {
+        anyhow::bail!("The username can't be empty.");
+    }
+    if password.expose_secret().len() < 12 {
+        anyhow::bail!("The password must be at least 12 characters long.");
+    }
+
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash the password: {e}"))?
+        .to_string();
+
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, created_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        username,
+        password_hash,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the admin user.")?;
+
+    Ok(user_id)
+}
+
+/// List the subscribers, optionally only those with the given status, oldest first.
+#[tracing::instrument(name = "List subscribers", skip(pool))]
+pub async fn list_subscribers(
+    pool: &PgPool,
+    status: Option<&str>,
+) -> Result<Vec<SubscriberSummary>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at
+        "#,
+        status,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the subscribers.")?;
+
+    Ok(subscribers)
+}
+
+/// Confirm a subscriber, returning `false` if there is no such subscriber.
+#[tracing::instrument(
+    name = "Confirm a subscriber from the CLI",
+    skip_all,
+    fields(subscriber_email = %pii(email))
+)]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let subscriber = sqlx::query!(
+        r#"
+        WITH previous AS (
+            SELECT id, status FROM subscriptions WHERE normalized_email = $1 FOR UPDATE
+        )
+        UPDATE subscriptions SET status = 'confirmed'
+        FROM previous
+        WHERE subscriptions.id = previous.id
+        RETURNING subscriptions.id, previous.status AS "previous_status!"
+        "#,
+        email.normalized(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to confirm the subscriber.")?;
+
+    let subscriber_id = match subscriber {
+        // Confirming a subscriber again doesn't change their consent.
+        Some(subscriber) if subscriber.previous_status == "confirmed" => return Ok(true),
+        Some(subscriber) => subscriber.id,
+        None => return Ok(false),
+    };
+
+    let context = ConsentContext {
+        ip_address: None,
+        user_agent: None,
+    };
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentEventKind::Confirm,
+        CONSENT_SOURCE,
+        &context,
+    )
+    .await
+    .context("Failed to record the confirmation of the subscriber.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+
+    Ok(true)
+}
+
+/// Delete a subscriber along with their data, returning `false` if there is no such
+/// subscriber.
+#[tracing::instrument(
+    name = "Delete a subscriber from the CLI",
+    skip_all,
+    fields(subscriber_email = %pii(email))
+)]
+pub async fn delete_subscriber(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let subscriber = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE normalized_email = $1",
+        email.normalized(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to query to the database.")?;
+
+    let subscriber_id = match subscriber {
+        Some(subscriber) => subscriber.id,
+        None => return Ok(false),
+    };
+
+    erase_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to erase the data of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")?;
+
+    Ok(true)
+}
+
+/// Queue again the deliveries that failed, optionally only those of an issue,
+/// returning how many of them were queued.
+#[tracing::instrument(name = "Requeue failed deliveries", skip(pool))]
+pub async fn requeue_failed_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Option<Uuid>,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'queued', failure_reason = NULL, updated_at = $2
+        WHERE status = 'failed' AND ($1::uuid IS NULL OR newsletter_issue_id = $1)
+        "#,
+        newsletter_issue_id,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to requeue the failed deliveries.")?;
+
+    Ok(result.rows_affected())
+}
+
+/// Send a test email, returning the ID that the email provider assigned to it.
+#[tracing::instrument(
+    name = "Send a test email",
+    skip_all,
+    fields(recipient = %pii(recipient))
+)]
+pub async fn send_test_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+) -> Result<Option<String>, anyhow::Error> {
+    email_client
+        .send_email(
+            recipient,
+            "Newsletter test email",
+            "<p>This is a test email sent from <code>newsletter-admin</code>.</p>",
+            "This is a test email sent from newsletter-admin.",
+        )
+        .await
+        .context("Failed to send the test email.")
+}
+
+/// Normalize again the stored emails of the subscribers and the suppressed addresses.
+///
+/// # Description
+///
+/// It must be run after changing the [EmailNormalization] of the settings, and
+/// once to replace the normalized emails that were backfilled by the migrations,
+/// which had their domain lowercased instead of IDNA-encoded. Subscribers whose new
+/// normalized email is already taken are reported and left as they were. Suppressed
+/// addresses are only stored normalized, so the case of their local part can't be
+/// restored once it was lowercased, and entries that become duplicates are merged.
+#[tracing::instrument(name = "Normalize the stored emails", skip(pool))]
+pub async fn normalize_emails(
+    pool: &PgPool,
+    normalization: EmailNormalization,
+) -> Result<NormalizationReport, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut report = NormalizationReport::default();
+
+    let subscribers = sqlx::query!("SELECT id, email, normalized_email FROM subscriptions")
+        .fetch_all(&mut transaction)
+        .await
+        .context("Failed to fetch the subscribers.")?;
+    for subscriber in subscribers {
+        let normalized = match SubscriberEmail::parse_with(subscriber.email.clone(), normalization)
+        {
+            Ok(email) => email.normalized().to_string(),
+            Err(e) => {
+                tracing::warn!(error.message = %e, "Skipping a subscriber with an invalid email");
+                continue;
+            }
+        };
+        if normalized == subscriber.normalized_email {
+            continue;
+        }
+
+        let updated = sqlx::query!(
+            r#"
+            UPDATE subscriptions SET normalized_email = $1
+            WHERE id = $2
+              AND NOT EXISTS (SELECT 1 FROM subscriptions WHERE normalized_email = $1)
+            "#,
+            normalized,
+            subscriber.id,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update the normalized email of a subscriber.")?
+        .rows_affected();
+        match updated {
+            0 => report.conflicts.push(subscriber.email),
+            _ => report.updated += 1,
+        }
+    }
+
+    let addresses = sqlx::query!("SELECT id, value FROM suppressions WHERE kind = 'address'")
+        .fetch_all(&mut transaction)
+        .await
+        .context("Failed to fetch the suppressed addresses.")?;
+    for address in addresses {
+        let normalized = match SubscriberEmail::parse_with(address.value.clone(), normalization) {
+            Ok(email) => email.normalized().to_string(),
+            Err(_) => continue,
+        };
+        if normalized == address.value {
+            continue;
+        }
+
+        // An equivalent entry may exist already, then this one is redundant.
+        sqlx::query!(
+            r#"
+            DELETE FROM suppressions
+            WHERE id = $2 AND EXISTS (SELECT 1 FROM suppressions WHERE value = $1)
+            "#,
+            normalized,
+            address.id,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to merge a suppressed address.")?;
+        report.updated += sqlx::query!(
+            "UPDATE suppressions SET value = $1 WHERE id = $2",
+            normalized,
+            address.id,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update a suppressed address.")?
+        .rows_affected();
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to normalize the emails.")?;
+
+    Ok(report)
+}
@@ -0,0 +1,177 @@
+//! Module that includes the authentication of the administrators.
+//!
+//! # Description
+//!
+//! The `/admin` endpoints require the credentials of one of the administrators
+//! created by `newsletter-admin create-admin`, sent using the HTTP Basic scheme.
+//! Passwords are checked against the Argon2 hashes stored in the `users` table.
+
+use crate::routes::{error_chain_fmt, json_error_response, ErrorBody};
+use actix_web::dev::Payload;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use base64::Engine;
+use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// Hash checked when the username is unknown, so that unknown users take as long
+/// to be rejected as wrong passwords.
+static FALLBACK_PASSWORD_HASH: Lazy<String> = Lazy::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(b"not-the-password-of-any-admin", &salt)
+        .expect("Failed to hash the fallback password.")
+        .to_string()
+});
+
+/// An administrator authenticated by the credentials of the request.
+#[derive(Debug)]
+pub struct AdminUser {
+    pub user_id: Uuid,
+}
+
+impl FromRequest for AdminUser {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let credentials = basic_authentication(req);
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+
+        Box::pin(async move {
+            let credentials = credentials?;
+            let pool = pool.context("The connection pool is not configured.")?;
+            let user_id = validate_credentials(credentials, &pool).await?;
+
+            Ok(AdminUser { user_id })
+        })
+    }
+}
+
+/// Credentials sent using the HTTP Basic scheme.
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Get the credentials of the `Authorization` header of a request.
+fn basic_authentication(req: &HttpRequest) -> Result<Credentials, AuthError> {
+    let encoded = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .ok_or(AuthError::MissingCredentials)?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or(AuthError::MissingCredentials)?;
+    let (username, password) = decoded
+        .split_once(':')
+        .ok_or(AuthError::MissingCredentials)?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+/// Check the credentials of an administrator, returning their ID.
+#[tracing::instrument(
+    name = "Validate the credentials of an admin",
+    skip_all,
+    fields(username = %credentials.username)
+)]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let user = sqlx::query!(
+        "SELECT user_id, password_hash FROM users WHERE username = $1",
+        credentials.username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the admin user.")?;
+
+    let (user_id, password_hash) = match user {
+        Some(user) => (Some(user.user_id), user.password_hash),
+        None => (None, FALLBACK_PASSWORD_HASH.clone()),
+    };
+
+    // Hashing the password is CPU-bound, keep it away from the async workers.
+    let valid = actix_web::rt::task::spawn_blocking(move || {
+        verify_password_hash(&password_hash, &credentials.password)
+    })
+    .await
+    .context("Failed to spawn a blocking task.")??;
+
+    match user_id {
+        Some(user_id) if valid => Ok(user_id),
+        _ => Err(AuthError::InvalidCredentials),
+    }
+}
+
+fn verify_password_hash(
+    password_hash: &str,
+    password: &Secret<String>,
+) -> Result<bool, anyhow::Error> {
+    let password_hash = PasswordHash::new(password_hash)
+        .map_err(|e| anyhow::anyhow!("The stored password hash is invalid: {e}"))?;
+
+    Ok(Argon2::default()
+        .verify_password(password.expose_secret().as_bytes(), &password_hash)
+        .is_ok())
+}
+
+#[derive(thiserror::Error)]
+pub enum AuthError {
+    #[error("The credentials of an administrator are required.")]
+    MissingCredentials,
+    #[error("The credentials are invalid.")]
+    InvalidCredentials,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::MissingCredentials | AuthError::InvalidCredentials => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            AuthError::UnexpectedError(_) => ErrorBody::internal(),
+            _ => ErrorBody::new(self.to_string(), Vec::new()),
+        };
+        let mut response = json_error_response(self.status_code(), body);
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="admin""#),
+            );
+        }
+
+        response
+    }
+}
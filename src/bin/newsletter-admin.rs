@@ -0,0 +1,148 @@
+//! Command line tool for the operation of the newsletter.
+//!
+//! It reads the same configuration as the API, so it must run with the same
+//! `APP_ENVIRONMENT` and `APP_*` variables as the API it operates on.
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use newsletter::admin;
+use newsletter::configuration::{get_configuration, Settings};
+use newsletter::startup::{get_connection_pool, run_migrations};
+use newsletter::telemetry::{get_subscriber, init_subscriber, set_pii_redaction};
+use newsletter::SubscriberEmail;
+use secrecy::Secret;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(name = "newsletter-admin", about = "Operate the newsletter service")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply the pending database migrations.
+    Migrate,
+    /// Create an administrator, whose credentials give access to the `/admin`
+    /// endpoints. The password is read from the standard input.
+    CreateAdmin { username: String },
+    /// List the subscribers.
+    ListSubscribers {
+        /// Only list the subscribers with this status, e.g. `confirmed`.
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Confirm a subscriber.
+    ConfirmSubscriber { email: String },
+    /// Delete a subscriber along with their data.
+    DeleteSubscriber { email: String },
+    /// Queue again the deliveries that failed.
+    RequeueFailed {
+        /// Only requeue the deliveries of this newsletter issue.
+        #[arg(long)]
+        issue: Option<Uuid>,
+    },
+    /// Send a test email through the email provider.
+    SendTestEmail { recipient: String },
+    /// Normalize the stored emails again, e.g. after changing
+    /// `email_verification.lowercase_local_part`.
+    NormalizeEmails,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let configuration = get_configuration().context("Failed to read configuration.")?;
+
+    // Logs go to stderr, so they don't get mixed with the output of the commands.
+    let subscriber = get_subscriber(
+        "newsletter-admin".into(),
+        "warn".into(),
+        std::io::stderr,
+        configuration.log.format,
+        None,
+    );
+    init_subscriber(subscriber);
+    set_pii_redaction(
+        configuration.log.redact_pii,
+        configuration.log.redaction_key.as_ref(),
+    );
+
+    execute(cli.command, configuration).await
+}
+
+async fn execute(command: Command, configuration: Settings) -> anyhow::Result<()> {
+    let pool = get_connection_pool(&configuration.database);
+
+    match command {
+        Command::Migrate => {
+            run_migrations(&configuration.database).await?;
+            println!("The database is up to date.");
+        }
+        Command::CreateAdmin { username } => {
+            let mut password = String::new();
+            std::io::stdin()
+                .read_line(&mut password)
+                .context("Failed to read the password.")?;
+            let password = Secret::new(password.trim_end_matches(['\r', '\n']).to_string());
+
+            let user_id = admin::create_admin_user(&pool, &username, password).await?;
+            println!("Created the admin user {username} ({user_id}).");
+        }
+        Command::ListSubscribers { status } => {
+            for s in admin::list_subscribers(&pool, status.as_deref()).await? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    s.email,
+                    s.name,
+                    s.status,
+                    s.locale,
+                    s.subscribed_at.to_rfc3339()
+                );
+            }
+        }
+        Command::ConfirmSubscriber { email } => {
+            let email = parse_email(email, &configuration)?;
+            if !admin::confirm_subscriber(&pool, &email).await? {
+                anyhow::bail!("{email} is not a subscriber.");
+            }
+            println!("Confirmed {email}.");
+        }
+        Command::DeleteSubscriber { email } => {
+            let email = parse_email(email, &configuration)?;
+            if !admin::delete_subscriber(&pool, &email).await? {
+                anyhow::bail!("{email} is not a subscriber.");
+            }
+            println!("Deleted {email}.");
+        }
+        Command::RequeueFailed { issue } => {
+            let count = admin::requeue_failed_deliveries(&pool, issue).await?;
+            println!("Requeued {count} failed deliveries.");
+        }
+        Command::SendTestEmail { recipient } => {
+            let recipient = parse_email(recipient, &configuration)?;
+            let email_client = configuration.email_client.client();
+            let message_id = admin::send_test_email(&email_client, &recipient).await?;
+            println!(
+                "Sent a test email to {recipient} (message ID: {}).",
+                message_id.as_deref().unwrap_or("unknown")
+            );
+        }
+        Command::NormalizeEmails => {
+            let normalization = configuration.email_verification.normalization();
+            let report = admin::normalize_emails(&pool, normalization).await?;
+            println!("Normalized {} emails.", report.updated);
+            for email in report.conflicts {
+                println!("{email} was left unchanged, as another subscriber has the same email.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_email(email: String, configuration: &Settings) -> anyhow::Result<SubscriberEmail> {
+    let normalization = configuration.email_verification.normalization();
+    SubscriberEmail::parse_with(email, normalization).map_err(anyhow::Error::msg)
+}
@@ -0,0 +1,319 @@
+//! Module that includes the checks to keep bots away from the subscription form.
+//!
+//! # Description
+//!
+//! Besides the honeypot field of the signup form, subscriptions go through two
+//! more checks before anything is stored:
+//! - A minimum time between the moment the form was rendered and its submission.
+//!   Humans need a few seconds to fill a form, scripts don't. The form includes the
+//!   time at which it was rendered, signed with HMAC-SHA256, so it can't be forged.
+//! - An optional captcha verification against a provider that implements the
+//!   usual `siteverify` API (hCaptcha, reCAPTCHA, Turnstile...).
+
+use crate::signed_token::SigningKey;
+use chrono::Utc;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+
+/// Checks applied to subscription requests.
+pub struct BotProtection {
+    min_submit_time: std::time::Duration,
+    form_key: Option<SigningKey>,
+    captcha_client: Option<CaptchaClient>,
+}
+
+impl BotProtection {
+    /// Build the checks.
+    ///
+    /// # Description
+    ///
+    /// A `form_key` is required to sign the render times of the forms, unless the
+    /// `min_submit_time` check is disabled by setting it to zero.
+    pub fn new(
+        min_submit_time: std::time::Duration,
+        form_key: Option<SigningKey>,
+        captcha_client: Option<CaptchaClient>,
+    ) -> Result<Self, String> {
+        if !min_submit_time.is_zero() && form_key.is_none() {
+            return Err("A key to sign the render time of the forms is required.".into());
+        }
+
+        Ok(Self {
+            min_submit_time,
+            form_key,
+            captcha_client,
+        })
+    }
+
+    /// Get the signed render time to include in a form that is rendered now, if the
+    /// minimum submit time is checked.
+    pub fn render_token(&self) -> Option<String> {
+        let key = self.form_key.as_ref()?;
+        let rendered_at = Utc::now().timestamp().to_string();
+
+        Some(format!("{rendered_at}.{}", key.sign(&rendered_at)))
+    }
+
+    /// Check whether a form was submitted faster than a human could fill it.
+    ///
+    /// # Description
+    ///
+    /// `render_token` is the signed render time that was included in the form by
+    /// [BotProtection::render_token]. When the check is enabled, requests without a
+    /// render time, or with one that isn't properly signed, are considered too fast:
+    /// nothing proves they waited.
+    pub fn is_too_fast(&self, render_token: Option<&str>) -> bool {
+        let Some(key) = self.form_key.as_ref() else {
+            return false;
+        };
+        if self.min_submit_time.is_zero() {
+            return false;
+        }
+
+        let rendered_at = render_token
+            .and_then(|token| token.split_once('.'))
+            .filter(|(rendered_at, signature)| key.verify(rendered_at, signature))
+            .and_then(|(rendered_at, _)| rendered_at.parse::<i64>().ok());
+
+        match rendered_at {
+            Some(rendered_at) => {
+                let elapsed = Utc::now().timestamp() - rendered_at;
+                elapsed < self.min_submit_time.as_secs() as i64
+            }
+            None => true,
+        }
+    }
+
+    /// Get the widget that the signup form must render, if captchas are enabled.
+    pub fn captcha_widget(&self) -> Option<&CaptchaWidget> {
+        self.captcha_client.as_ref().map(|client| &client.widget)
+    }
+
+    /// Verify the captcha response sent along a subscription, if captchas are enabled.
+    pub async fn verify_captcha(&self, response: Option<&str>) -> Result<bool, reqwest::Error> {
+        match (&self.captcha_client, response) {
+            (None, _) => Ok(true),
+            (Some(_), None) => Ok(false),
+            (Some(client), Some(response)) => client.verify(response).await,
+        }
+    }
+}
+
+/// Widget of a captcha provider, to be rendered in the signup form.
+///
+/// # Description
+///
+/// Providers load their widget with a script, and render it in the elements that
+/// have a given class. The `site_key` is the public counterpart of the secret key
+/// used by the [CaptchaClient].
+#[derive(Clone, Debug)]
+pub struct CaptchaWidget {
+    pub script_url: String,
+    pub class: String,
+    pub site_key: String,
+}
+
+/// Client for the verification API of a captcha provider.
+pub struct CaptchaClient {
+    http_client: Client,
+    base_url: String,
+    secret_key: Secret<String>,
+    widget: CaptchaWidget,
+}
+
+impl CaptchaClient {
+    pub fn new(
+        base_url: String,
+        secret_key: Secret<String>,
+        widget: CaptchaWidget,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+
+        Self {
+            http_client,
+            base_url,
+            secret_key,
+            widget,
+        }
+    }
+
+    /// Ask the provider whether a captcha response is valid.
+    pub async fn verify(&self, response: &str) -> Result<bool, reqwest::Error> {
+        let url = format!("{}/siteverify", self.base_url);
+        let outcome = self
+            .http_client
+            .post(&url)
+            .form(&[
+                ("secret", self.secret_key.expose_secret().as_str()),
+                ("response", response),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<VerifyResponse>()
+            .await?;
+
+        Ok(outcome.success)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct VerifyResponse {
+    success: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BotProtection, CaptchaClient, CaptchaWidget};
+    use crate::signed_token::SigningKey;
+    use claim::{assert_err, assert_ok_eq};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Get a test instance of `CaptchaClient`
+    fn captcha_client(base_url: String) -> CaptchaClient {
+        CaptchaClient::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            CaptchaWidget {
+                script_url: "https://captcha.example.com/api.js".into(),
+                class: "captcha".into(),
+                site_key: "a-site-key".into(),
+            },
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    fn form_key() -> SigningKey {
+        SigningKey::new(
+            "signup-form".into(),
+            Secret::new("a-very-long-secret-to-sign-the-forms".into()),
+        )
+        .unwrap()
+    }
+
+    fn bot_protection(captcha_client: Option<CaptchaClient>) -> BotProtection {
+        BotProtection::new(
+            std::time::Duration::from_secs(3),
+            Some(form_key()),
+            captcha_client,
+        )
+        .unwrap()
+    }
+
+    /// Sign a render time, as [BotProtection::render_token] does.
+    fn render_token(rendered_at: i64) -> String {
+        let rendered_at = rendered_at.to_string();
+        format!("{rendered_at}.{}", form_key().sign(&rendered_at))
+    }
+
+    #[test]
+    fn forms_submitted_too_fast_are_detected() {
+        let protection = bot_protection(None);
+        let token = protection.render_token().unwrap();
+        assert!(protection.is_too_fast(Some(&token)));
+    }
+
+    #[test]
+    fn forms_submitted_after_the_minimum_time_are_accepted() {
+        let now = chrono::Utc::now().timestamp();
+        assert!(!bot_protection(None).is_too_fast(Some(&render_token(now - 10))));
+    }
+
+    #[test]
+    fn requests_without_render_time_are_rejected() {
+        assert!(bot_protection(None).is_too_fast(None));
+    }
+
+    #[test]
+    fn forged_render_times_are_rejected() {
+        let now = chrono::Utc::now().timestamp();
+        let forged = render_token(now).replace(&now.to_string(), &(now - 10).to_string());
+
+        assert!(bot_protection(None).is_too_fast(Some(&forged)));
+        assert!(bot_protection(None).is_too_fast(Some(&(now - 10).to_string())));
+    }
+
+    #[test]
+    fn render_times_are_not_required_when_the_check_is_disabled() {
+        let protection = BotProtection::new(std::time::Duration::ZERO, None, None).unwrap();
+
+        assert!(protection.render_token().is_none());
+        assert!(!protection.is_too_fast(None));
+    }
+
+    #[test]
+    fn a_form_key_is_required_when_the_check_is_enabled() {
+        assert!(BotProtection::new(std::time::Duration::from_secs(3), None, None).is_err());
+    }
+
+    #[actix_web::test]
+    async fn captchas_are_not_required_when_disabled() {
+        assert_ok_eq!(bot_protection(None).verify_captcha(None).await, true);
+    }
+
+    #[actix_web::test]
+    async fn missing_captcha_responses_are_rejected_when_enabled() {
+        let mock_server = MockServer::start().await;
+        let protection = bot_protection(Some(captcha_client(mock_server.uri())));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok_eq!(protection.verify_captcha(None).await, false);
+    }
+
+    #[actix_web::test]
+    async fn verify_sends_the_response_to_the_provider() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(path("/siteverify"))
+            .and(method("POST"))
+            .and(body_string_contains("response=a-captcha-response"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": true})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok_eq!(client.verify("a-captcha-response").await, true);
+    }
+
+    #[actix_web::test]
+    async fn verify_returns_false_for_rejected_responses() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": false})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok_eq!(client.verify("a-captcha-response").await, false);
+    }
+
+    #[actix_web::test]
+    async fn verify_fails_if_the_provider_returns_500() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(client.verify("a-captcha-response").await);
+    }
+}
@@ -0,0 +1,224 @@
+//! Module that includes the audit log of the consent of subscribers.
+//!
+//! # Description
+//!
+//! Every change in the consent of a subscriber (subscribing, confirming,
+//! unsubscribing or changing their preferences) is recorded in the `consent_events`
+//! table, along with when it happened, the IP address and user agent of the request
+//! and the source that was used. The table is append-only: events are never
+//! updated, and they are only deleted along with the subscriber when their data is
+//! erased.
+
+use actix_web::dev::Payload;
+use actix_web::http::header::USER_AGENT;
+use actix_web::{web, FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgExecutor;
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Kind of change in the consent of a subscriber.
+///
+/// # Description
+///
+/// Unsubscriptions and preference changes are recorded by the flows that will
+/// handle them, there is none yet.
+#[derive(Debug, Clone, Copy)]
+pub enum ConsentEventKind {
+    Subscribe,
+    Confirm,
+    Unsubscribe,
+    PreferencesChange,
+}
+
+impl ConsentEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventKind::Subscribe => "subscribe",
+            ConsentEventKind::Confirm => "confirm",
+            ConsentEventKind::Unsubscribe => "unsubscribe",
+            ConsentEventKind::PreferencesChange => "preferences_change",
+        }
+    }
+}
+
+/// Reverse proxies whose `X-Forwarded-For` header is trusted.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// Get the address of the client that sent a request.
+    ///
+    /// # Description
+    ///
+    /// The address of the peer is used, unless it is a trusted proxy. In that case,
+    /// the `X-Forwarded-For` header is walked from the right, as every proxy appends
+    /// the address it got the request from, and the first address that isn't a
+    /// trusted proxy is the client. Addresses to the left of it may have been
+    /// written by the client itself, so they are never used.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let mut client = req.peer_addr()?.ip();
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+
+        for hop in forwarded.into_iter().rev() {
+            if !self.0.contains(&client) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(hop) => client = hop,
+                Err(_) => break,
+            }
+        }
+
+        Some(client)
+    }
+}
+
+/// Details of the request through which a subscriber gave consent.
+///
+/// # Description
+///
+/// The IP address is the address of the peer, or the one reported by the
+/// `X-Forwarded-For` header when the peer is one of the [TrustedProxies].
+#[derive(Debug)]
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for ConsentContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let ip_address = match req.app_data::<web::Data<TrustedProxies>>() {
+            Some(trusted_proxies) => trusted_proxies.client_ip(req),
+            None => TrustedProxies::default().client_ip(req),
+        }
+        .map(|ip| ip.to_string());
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+
+        ready(Ok(Self {
+            ip_address,
+            user_agent,
+        }))
+    }
+}
+
+/// An entry of the consent audit log.
+#[derive(Debug, serde::Serialize)]
+pub struct ConsentEvent {
+    pub kind: String,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Append an event to the consent audit log of a subscriber.
+#[tracing::instrument(name = "Record a consent event", skip(executor, context))]
+pub async fn record_consent_event(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    kind: ConsentEventKind,
+    source: &str,
+    context: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            consent_event_id,
+            subscriber_id,
+            kind,
+            source,
+            ip_address,
+            user_agent,
+            occurred_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        kind.as_str(),
+        source,
+        context.ip_address,
+        context.user_agent,
+        Utc::now(),
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the consent audit log of a subscriber, oldest events first.
+#[tracing::instrument(name = "Get the consent events of a subscriber", skip(executor))]
+pub async fn get_consent_events(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEvent,
+        r#"
+        SELECT kind, source, ip_address, user_agent, occurred_at
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrustedProxies;
+    use actix_web::test::TestRequest;
+    use claim::assert_some_eq;
+    use std::net::{IpAddr, SocketAddr};
+
+    const PROXY: &str = "10.0.0.1";
+
+    fn request(peer: &str, forwarded_for: &str) -> actix_web::HttpRequest {
+        TestRequest::default()
+            .peer_addr(SocketAddr::new(peer.parse().unwrap(), 4000))
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .to_http_request()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_without_trusted_proxies() {
+        let req = request("203.0.113.7", "198.51.100.1");
+        assert_some_eq!(TrustedProxies::default().client_ip(&req), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_address_forwarded_by_a_trusted_proxy_is_used() {
+        let proxies = TrustedProxies(vec![ip(PROXY)]);
+        let req = request(PROXY, "198.51.100.1");
+        assert_some_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn addresses_written_by_the_client_are_ignored() {
+        let proxies = TrustedProxies(vec![ip(PROXY), ip("10.0.0.2")]);
+        // The client sent a forged header, and two proxies appended to it.
+        let req = request(PROXY, "192.0.2.66, 198.51.100.1, 10.0.0.2");
+        assert_some_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::i18n::Locale;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub locale: Locale,
 }
@@ -1,15 +1,124 @@
 use validator::validate_email;
 
+/// Characters allowed in the local part of an email, besides the non-ASCII ones.
+const LOCAL_PART_SPECIAL_CHARACTERS: &str = ".!#$%&'*+/=?^_`{|}~-";
+
+/// Maximum length of the local part of an email, in bytes (RFC 5321).
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+
+/// Email of a subscriber.
+///
+/// # Description
+///
+/// Internationalized emails are supported: the local part may include non-ASCII
+/// characters (SMTPUTF8, RFC 6531) and the domain may be a Unicode domain name.
+/// Three forms of the email are kept:
+/// - The display form, which is the email as it was given, except for domains given
+///   in punycode, which are shown in their Unicode form. It's the form returned by
+///   [AsRef] and [std::fmt::Display].
+/// - The transport form ([SubscriberEmail::transport]), which has the domain
+///   IDNA-encoded (punycode), as expected by mail servers.
+/// - The normalized form ([SubscriberEmail::normalized]), which is used to compare
+///   emails: it is the transport form, whose domain is always lowercased. The local
+///   part is only lowercased when the [EmailNormalization] says so.
 #[derive(Debug)]
-pub struct SubscriberEmail(String);
+pub struct SubscriberEmail {
+    email: String,
+    transport: String,
+    normalized: String,
+}
+
+/// How the emails are normalized to compare them.
+///
+/// # Description
+///
+/// RFC 5321 lets mail servers treat the local part of an email as case-sensitive,
+/// so it's kept as it is by default. Most providers ignore its case, though, so
+/// lowercasing it can be enabled to consider `Jane@example.com` and
+/// `jane@example.com` the same subscriber.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmailNormalization {
+    pub lowercase_local_part: bool,
+}
 
 impl SubscriberEmail {
+    /// Parse an email, keeping the case of its local part in the normalized form.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+        Self::parse_with(s, EmailNormalization::default())
+    }
+
+    /// Parse an email, normalizing it as configured.
+    pub fn parse_with(
+        s: String,
+        normalization: EmailNormalization,
+    ) -> Result<SubscriberEmail, String> {
+        let invalid = || "The subscriber email is not valid.".to_string();
+
+        let (local_part, domain) = s.rsplit_once('@').ok_or_else(invalid)?;
+        if !is_valid_local_part(local_part) || !validate_email(format!("user@{domain}")) {
+            return Err(invalid());
         }
+        let ascii_domain = domain_to_ascii(domain).ok_or_else(invalid)?;
+        let unicode_domain = domain_to_unicode(domain);
+        let normalized_local_part = match normalization.lowercase_local_part {
+            true => local_part.to_lowercase(),
+            false => local_part.to_string(),
+        };
+
+        Ok(Self {
+            email: format!("{local_part}@{unicode_domain}"),
+            transport: format!("{local_part}@{ascii_domain}"),
+            normalized: format!("{normalized_local_part}@{}", ascii_domain.to_lowercase()),
+        })
+    }
+
+    /// Get the domain part of the email, i.e. everything after the last `@`.
+    pub fn domain(&self) -> &str {
+        self.email
+            .rsplit_once('@')
+            .map(|(_, d)| d)
+            .unwrap_or_default()
+    }
+
+    /// Get the form of the email that must be used to send emails to it.
+    pub fn transport(&self) -> &str {
+        &self.transport
+    }
+
+    /// Get the normalized form of the email, used to compare emails.
+    pub fn normalized(&self) -> &str {
+        &self.normalized
+    }
+}
+
+fn is_valid_local_part(local_part: &str) -> bool {
+    !local_part.is_empty()
+        && local_part.len() <= MAX_LOCAL_PART_LENGTH
+        && local_part.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+                || LOCAL_PART_SPECIAL_CHARACTERS.contains(c)
+                || (!c.is_ascii() && !c.is_control() && !c.is_whitespace())
+        })
+}
+
+/// Convert a domain to its IDNA-encoded form. Address literals are kept as they are.
+fn domain_to_ascii(domain: &str) -> Option<String> {
+    if domain.starts_with('[') {
+        return Some(domain.to_string());
+    }
+
+    idna::domain_to_ascii(domain).ok()
+}
+
+/// Convert a domain to its Unicode form, when it was given in punycode.
+fn domain_to_unicode(domain: &str) -> String {
+    let is_punycode = domain
+        .split('.')
+        .any(|label| label.to_ascii_lowercase().starts_with("xn--"));
+
+    match is_punycode {
+        true => idna::domain_to_unicode(domain).0,
+        false => domain.to_string(),
     }
 }
 
@@ -17,19 +126,19 @@ impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // We just forward to the Display implementation of
         // the wrapped String.
-        self.0.fmt(f)
+        self.email.fmt(f)
     }
 }
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.email
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberEmail;
+    use super::{EmailNormalization, SubscriberEmail};
     use claim::assert_err;
     use fake::{faker::internet::en::SafeEmail, Fake};
 
@@ -43,6 +152,28 @@ mod tests {
         }
     }
 
+    /// Lowercase letters, both ASCII and non-ASCII, to build internationalized emails.
+    const LETTERS: &[char] = &[
+        'a', 'e', 'j', 'o', 'z', 'á', 'é', 'ñ', 'ü', 'ç', 'ø', 'å', 'ß', 'д', 'ж', 'я', 'α', 'λ',
+        'ω', '日', '本', '語', '中', '文',
+    ];
+
+    #[derive(Debug, Clone)]
+    struct InternationalEmailFixture(pub String);
+
+    impl quickcheck::Arbitrary for InternationalEmailFixture {
+        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+            let word = |g: &mut G| -> String {
+                let length = 1 + usize::arbitrary(g) % 10;
+                (0..length)
+                    .map(|_| LETTERS[usize::arbitrary(g) % LETTERS.len()])
+                    .collect()
+            };
+            let email = format!("{}@{}.{}", word(g), word(g), word(g));
+            Self(email)
+        }
+    }
+
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
@@ -61,8 +192,105 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn domain_is_extracted_from_a_valid_email() {
+        let email = SubscriberEmail::parse("jane.doe@mail.com".to_string()).unwrap();
+        assert_eq!(email.domain(), "mail.com");
+    }
+
+    #[test]
+    fn the_original_email_is_kept() {
+        let email = SubscriberEmail::parse("Jane.Doe@Mail.com".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Jane.Doe@Mail.com");
+    }
+
+    #[test]
+    fn normalized_email_keeps_the_case_of_the_local_part_by_default() {
+        let email = SubscriberEmail::parse("Jane.Doe@Mail.COM".to_string()).unwrap();
+        assert_eq!(email.normalized(), "Jane.Doe@mail.com");
+    }
+
+    #[test]
+    fn normalized_email_is_lowercased_when_enabled() {
+        let normalization = EmailNormalization {
+            lowercase_local_part: true,
+        };
+        let email =
+            SubscriberEmail::parse_with("Jane.Doe@Mail.COM".to_string(), normalization).unwrap();
+        assert_eq!(email.normalized(), "jane.doe@mail.com");
+    }
+
+    #[test]
+    fn normalized_email_has_an_idna_encoded_domain() {
+        let email = SubscriberEmail::parse("jane@bücher.de".to_string()).unwrap();
+        assert_eq!(email.normalized(), "jane@xn--bcher-kva.de");
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn normalizing_is_idempotent(valid_email: ValidEmailFixture) -> bool {
+        let email = SubscriberEmail::parse(valid_email.0).unwrap();
+        let normalized = SubscriberEmail::parse(email.normalized().to_string()).unwrap();
+        email.normalized() == normalized.normalized()
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
+
+    #[test]
+    fn email_with_whitespace_in_the_local_part_is_rejected() {
+        let email = "jane doe@mail.com".to_string();
+        assert_err!(SubscriberEmail::parse(email));
+    }
+
+    #[test]
+    fn email_with_a_too_long_local_part_is_rejected() {
+        let email = format!("{}@mail.com", "a".repeat(65));
+        assert_err!(SubscriberEmail::parse(email));
+    }
+
+    #[test]
+    fn email_with_a_unicode_local_part_is_accepted() {
+        let email = SubscriberEmail::parse("josé@mail.com".to_string()).unwrap();
+        assert_eq!(email.transport(), "josé@mail.com");
+    }
+
+    #[test]
+    fn unicode_domains_are_idna_encoded_for_transport() {
+        let email = SubscriberEmail::parse("jane@bücher.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "jane@bücher.de");
+        assert_eq!(email.transport(), "jane@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn punycode_domains_are_displayed_in_their_unicode_form() {
+        let email = SubscriberEmail::parse("jane@xn--bcher-kva.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "jane@bücher.de");
+        assert_eq!(email.domain(), "bücher.de");
+        assert_eq!(email.transport(), "jane@xn--bcher-kva.de");
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn international_emails_are_parsed_successfully(email: InternationalEmailFixture) -> bool {
+        SubscriberEmail::parse(email.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn international_emails_have_an_ascii_domain_for_transport(
+        email: InternationalEmailFixture,
+    ) -> bool {
+        let email = SubscriberEmail::parse(email.0).unwrap();
+        let (_, domain) = email.transport().rsplit_once('@').unwrap();
+        domain.is_ascii()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn international_emails_round_trip_through_their_transport_form(
+        email: InternationalEmailFixture,
+    ) -> bool {
+        let email = SubscriberEmail::parse(email.0).unwrap();
+        let parsed_again = SubscriberEmail::parse(email.transport().to_string()).unwrap();
+        email.as_ref() == parsed_again.as_ref() && email.normalized() == parsed_again.normalized()
+    }
 }
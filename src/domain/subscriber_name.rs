@@ -11,7 +11,7 @@ impl SubscriberName {
         let contains_forbidden_chars = s.chars().any(|g| forbidden_characters.contains(&g));
 
         if is_empty_or_whitespace || is_too_long || contains_forbidden_chars {
-            Err(format!("{s} is not a valid subscriber name."))
+            Err("The subscriber name is not valid.".to_string())
         } else {
             Ok(Self(s))
         }
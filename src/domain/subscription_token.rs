@@ -0,0 +1,120 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// Minimum length of the tokens. Alphanumeric tokens of this length have ~95 bits
+/// of entropy.
+pub const MIN_TOKEN_LENGTH: usize = 16;
+
+/// Maximum length of the tokens that are accepted from clients.
+const MAX_TOKEN_LENGTH: usize = 128;
+
+/// Token that is emailed to a subscriber to let them confirm their subscription,
+/// or to access their data.
+///
+/// # Description
+///
+/// Tokens are never stored: only their SHA-256 [hash](SubscriptionToken::hash) is.
+/// Tokens are looked up by their hash, so a leak of the DB doesn't leak usable
+/// tokens, and the comparison that the DB performs doesn't tell anything about the
+/// token itself, which keeps the lookup safe from timing attacks.
+#[derive(Debug)]
+pub struct SubscriptionToken(String);
+
+impl SubscriptionToken {
+    /// Parse a token that was received from a client.
+    pub fn parse(s: String) -> Result<SubscriptionToken, String> {
+        let has_valid_length = (MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&s.len());
+        let is_alphanumeric = s.chars().all(|c| c.is_ascii_alphanumeric());
+
+        if has_valid_length && is_alphanumeric {
+            Ok(Self(s))
+        } else {
+            Err("The token is not valid.".to_string())
+        }
+    }
+
+    /// Get the hex-encoded SHA-256 hash of the token, which is what gets stored.
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl AsRef<str> for SubscriptionToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Generator of random alphanumeric tokens of a given length.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenGenerator {
+    length: usize,
+}
+
+impl TokenGenerator {
+    pub fn new(length: usize) -> Result<TokenGenerator, String> {
+        if (MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&length) {
+            Ok(Self { length })
+        } else {
+            Err(format!(
+                "The length of the tokens must be between {MIN_TOKEN_LENGTH} and \
+                {MAX_TOKEN_LENGTH} characters, got {length}."
+            ))
+        }
+    }
+
+    pub fn generate(&self) -> SubscriptionToken {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(self.length)
+            .collect();
+
+        SubscriptionToken(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubscriptionToken, TokenGenerator, MIN_TOKEN_LENGTH};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn generated_tokens_have_the_configured_length() {
+        let token = TokenGenerator::new(40).unwrap().generate();
+        assert_eq!(token.as_ref().len(), 40);
+        assert!(token.as_ref().chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn generators_of_short_tokens_are_rejected() {
+        assert_err!(TokenGenerator::new(MIN_TOKEN_LENGTH - 1));
+        assert_ok!(TokenGenerator::new(MIN_TOKEN_LENGTH));
+    }
+
+    #[test]
+    fn generated_tokens_can_be_parsed() {
+        let token = TokenGenerator::new(25).unwrap().generate();
+        assert_ok!(SubscriptionToken::parse(token.as_ref().to_string()));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        assert_err!(SubscriptionToken::parse("short".to_string()));
+        assert_err!(SubscriptionToken::parse(
+            "not-an-alphanumeric-token".to_string()
+        ));
+        assert_err!(SubscriptionToken::parse("a".repeat(129)));
+    }
+
+    #[test]
+    fn tokens_are_hashed_with_sha256() {
+        let token = SubscriptionToken::parse("abcdefghijklmnopqrstuvwxy".to_string()).unwrap();
+        let hash = token.hash();
+
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(hash, token.hash());
+        assert_ne!(hash, token.as_ref());
+    }
+}
@@ -1,12 +1,37 @@
 use crate::domain::SubscriberEmail;
+use crate::metrics::record_email_send;
+use crate::telemetry::trace_context_headers;
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
+use std::sync::Mutex;
+use std::time::Instant;
 
 pub struct EmailClient {
     pub http_client: Client,
     pub base_url: String,
     pub sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    provider_status: Mutex<ProviderStatus>,
+}
+
+/// When the email provider last accepted and last rejected an email sent by a
+/// client.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct ProviderStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+}
+
+impl ProviderStatus {
+    /// Whether the latest email that was sent failed.
+    pub fn is_failing(&self) -> bool {
+        match (self.last_success, self.last_failure) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(success), Some(failure)) => failure > success,
+        }
+    }
 }
 
 impl EmailClient {
@@ -23,39 +48,85 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            provider_status: Mutex::new(ProviderStatus::default()),
         }
     }
 
+    /// Get when the email provider last accepted and rejected an email sent by
+    /// this client.
+    pub fn provider_status(&self) -> ProviderStatus {
+        *self.provider_status.lock().unwrap()
+    }
+
+    /// Send an email through the provider's API.
+    ///
+    /// # Description
+    ///
+    /// On success, the ID that the provider assigned to the message is returned when
+    /// the provider's response includes it. The outcome is recorded in the
+    /// [ProviderStatus] of the client.
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<Option<String>, reqwest::Error> {
+        let started = Instant::now();
+        let outcome = self
+            .try_send_email(recipient, subject, html_content, text_content)
+            .await;
+        record_email_send(outcome.is_ok(), started.elapsed());
+
+        let mut status = self.provider_status.lock().unwrap();
+        match outcome {
+            Ok(_) => status.last_success = Some(Utc::now()),
+            Err(_) => status.last_failure = Some(Utc::now()),
+        }
+
+        outcome
+    }
+
+    async fn try_send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Option<String>, reqwest::Error> {
         // Point to the API endpoint for sending emails.
         let url = format!("{}/email", self.base_url);
         // Build a JSON to send along the POST.
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
+            from: self.sender.transport(),
+            to: recipient.transport(),
             subject,
             html_body: html_content,
             text_body: text_content,
         };
-        let _builder = self
+        let request = self
             .http_client
             .post(&url)
             .header(
                 "X-Postmark-Server-Token",
                 self.authorization_token.expose_secret(),
             )
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
+            .json(&request_body);
+        // Let the provider join the trace of the request that sends the email.
+        let request = trace_context_headers()
+            .into_iter()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            });
+        let response = request.send().await?.error_for_status()?;
+
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.message_id);
 
-        Ok(())
+        Ok(message_id)
     }
 }
 
@@ -69,6 +140,12 @@ struct SendEmailRequest<'a> {
     text_body: &'a str,
 }
 
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
@@ -78,7 +155,7 @@ mod tests {
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::Request;
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
@@ -175,6 +252,50 @@ mod tests {
         // the test will yield an ok result, if not, the test will yield a fail result.
     }
 
+    #[actix_web::test]
+    async fn send_email_returns_the_provider_message_id() {
+        // Prepare
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"MessageID": "a-message-id"})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Go for the actual test.
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_eq!(outcome.unwrap(), Some("a-message-id".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn send_email_uses_the_idna_encoded_domain_of_the_recipient() {
+        // Prepare
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = SubscriberEmail::parse("josé@bücher.de".to_string()).unwrap();
+
+        Mock::given(body_partial_json(
+            serde_json::json!({"To": "josé@xn--bcher-kva.de"}),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // Go for the actual test.
+        let _ = email_client
+            .send_email(&recipient, &subject(), &content(), &content())
+            .await;
+    }
+
     #[actix_web::test]
     async fn send_email_fails_if_the_server_returns_500() {
         // Prepare
@@ -195,6 +316,39 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[actix_web::test]
+    async fn send_email_records_the_outcome_in_the_provider_status() {
+        // Prepare
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        assert!(!email_client.provider_status().is_failing());
+
+        let failing_mock = Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount_as_scoped(&mock_server)
+            .await;
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let failed = email_client.provider_status();
+        drop(failing_mock);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let recovered = email_client.provider_status();
+
+        // Check
+        assert!(failed.is_failing());
+        assert!(failed.last_success.is_none());
+        assert!(!recovered.is_failing());
+        assert!(recovered.last_success.is_some());
+    }
+
     #[actix_web::test]
     async fn send_email_times_out_if_the_server_takes_too_long() {
         // Prepare
@@ -0,0 +1,350 @@
+//! Module that includes the verification stage of the subscribers' emails.
+//!
+//! # Description
+//!
+//! [SubscriberEmail::parse] only checks the syntax of an email. The [EmailVerifier]
+//! goes a step further and checks, when enabled in the configuration:
+//! - That the domain of the email is not a known typo of a common email provider.
+//! - That the domain is not a disposable email provider.
+//! - That the domain can receive emails, i.e. it has MX records or, at least, an
+//!   address record to fall back to.
+//!
+//! DNS lookups are performed through the [MxResolver] trait, so they can be replaced
+//! by a fake resolver in tests.
+
+use crate::domain::SubscriberEmail;
+use crate::telemetry::pii;
+use async_trait::async_trait;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Domains of common email providers, used to detect typos.
+const COMMON_DOMAINS: &[&str] = &[
+    "aol.com",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "googlemail.com",
+    "hotmail.com",
+    "icloud.com",
+    "live.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "yahoo.com",
+    "yandex.com",
+];
+
+/// Minimum length of the name of a common domain, without its top-level domain, for
+/// it to be suggested.
+const MIN_NAME_LENGTH: usize = 5;
+
+/// Real email domains that are close to a common domain, so they must not be taken
+/// for a typo of it.
+const KNOWN_DOMAINS: &[&str] = &[
+    "aim.com",
+    "att.com",
+    "email.com",
+    "ge.com",
+    "gmx.at",
+    "gmx.ch",
+    "gmx.fr",
+    "gmx.net",
+    "gmx.us",
+    "live.ca",
+    "live.de",
+    "live.fr",
+    "mac.com",
+    "mail.de",
+    "me.de",
+    "msn.de",
+    "web.de",
+    "ymail.com",
+];
+
+/// Resolver that tells whether a domain can receive emails.
+#[async_trait]
+pub trait MxResolver: Send + Sync {
+    /// Check whether a domain has MX records, or an address record to fall back to.
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error>;
+}
+
+/// [MxResolver] that uses the DNS servers of the system.
+pub struct DnsMxResolver(TokioAsyncResolver);
+
+impl DnsMxResolver {
+    pub fn from_system_conf() -> Result<Self, anyhow::Error> {
+        Ok(Self(TokioAsyncResolver::tokio_from_system_conf()?))
+    }
+}
+
+#[async_trait]
+impl MxResolver for DnsMxResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        // Make the name fully qualified, so no search domain gets appended.
+        let fqdn = format!("{}.", domain.trim_end_matches('.'));
+
+        match self.0.mx_lookup(fqdn.as_str()).await {
+            Ok(records) if records.iter().next().is_some() => return Ok(true),
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // RFC 5321: with no MX records, the domain itself is the mail server.
+        match self.0.lookup_ip(fqdn.as_str()).await {
+            Ok(ips) => Ok(ips.iter().next().is_some()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Errors found while verifying an email.
+#[derive(thiserror::Error, Debug)]
+pub enum EmailVerificationError {
+    #[error("The domain {domain} looks like a typo. Did you mean {suggestion}?")]
+    LikelyTypo { domain: String, suggestion: String },
+    #[error("The domain {0} belongs to a disposable email provider.")]
+    DisposableDomain(String),
+    #[error("The domain {0} can't receive emails.")]
+    CannotReceiveMail(String),
+}
+
+/// Verification stage for the emails of new subscribers.
+pub struct EmailVerifier {
+    suggest_typos: bool,
+    disposable_domains: HashSet<String>,
+    resolver: Option<Arc<dyn MxResolver>>,
+}
+
+impl EmailVerifier {
+    pub fn new(
+        suggest_typos: bool,
+        disposable_domains: Vec<String>,
+        resolver: Option<Arc<dyn MxResolver>>,
+    ) -> Self {
+        Self {
+            suggest_typos,
+            disposable_domains: disposable_domains
+                .into_iter()
+                .map(|d| d.to_lowercase())
+                .collect(),
+            resolver,
+        }
+    }
+
+    /// Run all the enabled checks on an email.
+    ///
+    /// # Description
+    ///
+    /// Lookup failures that are not caused by the domain itself (timeouts,
+    /// unreachable DNS servers...) don't reject the email: they are logged and the
+    /// email is accepted, so an outage of the DNS doesn't block subscriptions.
+    #[tracing::instrument(
+        name = "Verify a subscriber email",
+        skip_all,
+        fields(subscriber_email = %pii(email))
+    )]
+    pub async fn verify(&self, email: &SubscriberEmail) -> Result<(), EmailVerificationError> {
+        let domain = email.domain().to_lowercase();
+
+        if self.suggest_typos {
+            if let Some(suggestion) = suggest_domain(&domain) {
+                return Err(EmailVerificationError::LikelyTypo {
+                    domain,
+                    suggestion: suggestion.to_string(),
+                });
+            }
+        }
+
+        if self.disposable_domains.contains(&domain) {
+            return Err(EmailVerificationError::DisposableDomain(domain));
+        }
+
+        if let Some(resolver) = &self.resolver {
+            match resolver.accepts_mail(&domain).await {
+                Ok(true) => {}
+                Ok(false) => return Err(EmailVerificationError::CannotReceiveMail(domain)),
+                Err(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Failed to look up the MX records of {domain}, accepting the email",
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Suggest a common email domain when the given one looks like a typo of it.
+///
+/// # Description
+///
+/// A domain is considered a typo when it is a single edit away from one of the
+/// common domains, e.g. _gmial.com_ for _gmail.com_. Common domains and the known
+/// real domains that are close to them, like _ymail.com_, are never typos.
+///
+/// Common domains with a short name, like _me.com_ or _gmx.de_, are never
+/// suggested: a single edit of them gives plenty of real domains, like _mi.com_ or
+/// _gmx.dk_.
+pub fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if COMMON_DOMAINS.contains(&domain) || KNOWN_DOMAINS.contains(&domain) {
+        return None;
+    }
+
+    COMMON_DOMAINS
+        .iter()
+        .filter(|common| common.split('.').next().unwrap_or_default().len() >= MIN_NAME_LENGTH)
+        .find(|common| edit_distance(domain, common) == 1)
+        .copied()
+}
+
+/// Damerau-Levenshtein distance (optimal string alignment) between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{suggest_domain, EmailVerificationError, EmailVerifier, MxResolver};
+    use crate::domain::SubscriberEmail;
+    use async_trait::async_trait;
+    use claim::{assert_err, assert_none, assert_ok};
+    use std::sync::Arc;
+
+    /// Resolver that only accepts mail for `mail.com`.
+    struct FakeResolver;
+
+    #[async_trait]
+    impl MxResolver for FakeResolver {
+        async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+            match domain {
+                "mail.com" => Ok(true),
+                "broken-dns.com" => Err(anyhow::anyhow!("DNS server unreachable")),
+                _ => Ok(false),
+            }
+        }
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn verifier() -> EmailVerifier {
+        EmailVerifier::new(
+            true,
+            vec!["Mailinator.com".to_string()],
+            Some(Arc::new(FakeResolver)),
+        )
+    }
+
+    #[test]
+    fn typos_of_common_domains_get_a_suggestion() {
+        assert_eq!(suggest_domain("gmial.com"), Some("gmail.com"));
+        assert_eq!(suggest_domain("hotmal.com"), Some("hotmail.com"));
+        assert_eq!(suggest_domain("yahoo.co"), Some("yahoo.com"));
+    }
+
+    #[test]
+    fn common_and_unrelated_domains_get_no_suggestion() {
+        assert_none!(suggest_domain("gmail.com"));
+        assert_none!(suggest_domain("mail.com"));
+        assert_none!(suggest_domain("nubecita.eu"));
+    }
+
+    #[test]
+    fn real_domains_close_to_common_ones_get_no_suggestion() {
+        for domain in [
+            "gmx.net",
+            "gmx.at",
+            "gmx.ch",
+            "ymail.com",
+            "email.com",
+            "mac.com",
+            "ge.com",
+            "aim.com",
+            "att.com",
+            "live.ca",
+        ] {
+            assert_none!(suggest_domain(domain), "{domain} was taken for a typo");
+        }
+    }
+
+    #[test]
+    fn short_domains_close_to_short_common_ones_get_no_suggestion() {
+        for domain in [
+            "mi.com", "ms.com", "mo.com", "mu.com", "msa.com", "gmx.dk", "gmx.be", "gmc.com",
+            "live.it",
+        ] {
+            assert_none!(suggest_domain(domain), "{domain} was taken for a typo");
+        }
+    }
+
+    #[actix_web::test]
+    async fn typos_are_rejected_with_a_suggestion() {
+        let outcome = verifier().verify(&email("jane@gmial.com")).await;
+
+        match outcome {
+            Err(EmailVerificationError::LikelyTypo { suggestion, .. }) => {
+                assert_eq!(suggestion, "gmail.com")
+            }
+            _ => panic!("The typo was not detected."),
+        }
+    }
+
+    #[actix_web::test]
+    async fn disposable_domains_are_rejected() {
+        assert_err!(verifier().verify(&email("jane@mailinator.com")).await);
+    }
+
+    #[actix_web::test]
+    async fn domains_that_cannot_receive_mail_are_rejected() {
+        assert_err!(verifier().verify(&email("jane@nubecita.eu")).await);
+    }
+
+    #[actix_web::test]
+    async fn domains_that_receive_mail_are_accepted() {
+        assert_ok!(verifier().verify(&email("jane@mail.com")).await);
+    }
+
+    #[actix_web::test]
+    async fn lookup_failures_do_not_reject_emails() {
+        assert_ok!(verifier().verify(&email("jane@broken-dns.com")).await);
+    }
+
+    #[actix_web::test]
+    async fn mx_records_are_not_checked_without_a_resolver() {
+        let verifier = EmailVerifier::new(false, Vec::new(), None);
+        assert_ok!(verifier.verify(&email("jane@nubecita.eu")).await);
+    }
+}
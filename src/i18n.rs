@@ -0,0 +1,330 @@
+//! Module that includes the localization of the emails and pages shown to
+//! subscribers.
+//!
+//! # Description
+//!
+//! Every subscriber has a [Locale], which is chosen when they subscribe: either
+//! explicitly, using the `locale` field of the signup form, or from the
+//! `Accept-Language` header of the request. Transactional emails are written using
+//! the [Catalog] of that locale, and newsletter issues can include a variant per
+//! locale, so each subscriber gets the issue in their language.
+
+use actix_web::http::header::{AcceptLanguage, Header, Preference};
+use actix_web::HttpRequest;
+
+/// Languages in which emails can be sent to subscribers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    Fr,
+    De,
+}
+
+impl Locale {
+    /// Every supported locale.
+    pub const ALL: [Locale; 4] = [Locale::En, Locale::Es, Locale::Fr, Locale::De];
+
+    /// Parse a locale from a language tag, e.g. `es` or `es-AR`.
+    ///
+    /// # Description
+    ///
+    /// Only the primary language of the tag is taken into account, so regional
+    /// variants get the catalog of their language.
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let language = tag.split(['-', '_']).next()?.trim().to_lowercase();
+
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.as_str() == language)
+    }
+
+    /// Choose the locale of a new subscriber.
+    ///
+    /// # Description
+    ///
+    /// The locale that is given explicitly wins over the one accepted by the
+    /// `Accept-Language` header of the request. When none of them is a supported
+    /// locale, the default locale is used.
+    pub fn negotiate(requested: Option<&str>, accepted: Option<Locale>) -> Locale {
+        requested
+            .and_then(Locale::parse)
+            .or(accepted)
+            .unwrap_or_default()
+    }
+
+    /// Get the preferred supported locale from the `Accept-Language` header.
+    pub fn from_accept_language(req: &HttpRequest) -> Option<Locale> {
+        AcceptLanguage::parse(req)
+            .ok()?
+            .ranked()
+            .into_iter()
+            .find_map(|preference| match preference {
+                Preference::Specific(tag) => Locale::parse(tag.primary_language()),
+                Preference::Any => None,
+            })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+            Locale::Fr => "fr",
+            Locale::De => "de",
+        }
+    }
+
+    /// Get the message catalog of the locale.
+    pub fn catalog(&self) -> &'static Catalog {
+        match self {
+            Locale::En => &EN,
+            Locale::Es => &ES,
+            Locale::Fr => &FR,
+            Locale::De => &DE,
+        }
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Messages of the transactional emails and of the pages shown to subscribers, in a
+/// given language.
+///
+/// # Description
+///
+/// The placeholders of the messages, like `{link}`, are replaced when the email is
+/// written.
+pub struct Catalog {
+    pub confirmation_subject: &'static str,
+    confirmation_text: &'static str,
+    confirmation_html: &'static str,
+    pub privacy_subject: &'static str,
+    privacy_text: &'static str,
+    privacy_html: &'static str,
+    pub export_subject: &'static str,
+    export_text: &'static str,
+    export_html: &'static str,
+    pub erasure_title: &'static str,
+    pub erasure_prompt: &'static str,
+    pub erasure_button: &'static str,
+}
+
+impl Catalog {
+    /// Write the plain text body of the confirmation email.
+    pub fn confirmation_text(&self, link: &str) -> String {
+        self.confirmation_text.replace("{link}", link)
+    }
+
+    /// Write the HTML body of the confirmation email.
+    pub fn confirmation_html(&self, link: &str) -> String {
+        self.confirmation_html.replace("{link}", link)
+    }
+
+    /// Write the plain text body of the email with the links to export or erase
+    /// the data of a subscriber.
+    pub fn privacy_text(&self, export_link: &str, erasure_link: &str) -> String {
+        self.privacy_text
+            .replace("{export_link}", export_link)
+            .replace("{erasure_link}", erasure_link)
+    }
+
+    /// Write the HTML body of the email with the links to export or erase the data
+    /// of a subscriber.
+    pub fn privacy_html(&self, export_link: &str, erasure_link: &str) -> String {
+        self.privacy_html
+            .replace("{export_link}", export_link)
+            .replace("{erasure_link}", erasure_link)
+    }
+
+    /// Write the plain text body of the email with the data export of a subscriber.
+    pub fn export_text(&self, export: &str) -> String {
+        self.export_text.replace("{export}", export)
+    }
+
+    /// Write the HTML body of the email with the data export of a subscriber.
+    pub fn export_html(&self, export: &str) -> String {
+        let export = export
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        self.export_html.replace("{export}", &export)
+    }
+}
+
+const EN: Catalog = Catalog {
+    confirmation_subject: "Welcome!",
+    confirmation_text: "Welcome to our newsletter!\n\
+        Visit {link} to confirm your subscription.",
+    confirmation_html: "Welcome to our newsletter!<br /> \
+        Click <a href=\"{link}\">here</a> to confirm your subscription.",
+    privacy_subject: "Your personal data",
+    privacy_text: "Visit {export_link} to get a copy of your personal data.\n\
+        Visit {erasure_link} to unsubscribe and erase all your personal data.\n\
+        If you didn't ask for this email, you can ignore it.",
+    privacy_html: "Click <a href=\"{export_link}\">here</a> to get a copy of your \
+        personal data.<br /> \
+        Click <a href=\"{erasure_link}\">here</a> to unsubscribe and erase all your \
+        personal data.<br /> \
+        If you didn't ask for this email, you can ignore it.",
+    export_subject: "A copy of your personal data",
+    export_text: "This is all the personal data we keep about you:\n\n{export}",
+    export_html: "This is all the personal data we keep about you:<pre>{export}</pre>",
+    erasure_title: "Erase your personal data",
+    erasure_prompt: "You will be unsubscribed, and all your personal data will be \
+        erased. This can't be undone.",
+    erasure_button: "Erase my data",
+};
+
+const ES: Catalog = Catalog {
+    confirmation_subject: "¡Bienvenido!",
+    confirmation_text: "¡Bienvenido a nuestro boletín!\n\
+        Visita {link} para confirmar tu suscripción.",
+    confirmation_html: "¡Bienvenido a nuestro boletín!<br /> \
+        Haz clic <a href=\"{link}\">aquí</a> para confirmar tu suscripción.",
+    privacy_subject: "Tus datos personales",
+    privacy_text: "Visita {export_link} para obtener una copia de tus datos personales.\n\
+        Visita {erasure_link} para darte de baja y borrar todos tus datos personales.\n\
+        Si no pediste este correo, puedes ignorarlo.",
+    privacy_html: "Haz clic <a href=\"{export_link}\">aquí</a> para obtener una copia \
+        de tus datos personales.<br /> \
+        Haz clic <a href=\"{erasure_link}\">aquí</a> para darte de baja y borrar todos \
+        tus datos personales.<br /> \
+        Si no pediste este correo, puedes ignorarlo.",
+    export_subject: "Una copia de tus datos personales",
+    export_text: "Estos son todos los datos personales que guardamos sobre ti:\n\n{export}",
+    export_html: "Estos son todos los datos personales que guardamos sobre ti:\
+        <pre>{export}</pre>",
+    erasure_title: "Borrar tus datos personales",
+    erasure_prompt: "Se cancelará tu suscripción y se borrarán todos tus datos \
+        personales. No se puede deshacer.",
+    erasure_button: "Borrar mis datos",
+};
+
+const FR: Catalog = Catalog {
+    confirmation_subject: "Bienvenue !",
+    confirmation_text: "Bienvenue dans notre newsletter !\n\
+        Rendez-vous sur {link} pour confirmer votre inscription.",
+    confirmation_html: "Bienvenue dans notre newsletter !<br /> \
+        Cliquez <a href=\"{link}\">ici</a> pour confirmer votre inscription.",
+    privacy_subject: "Vos données personnelles",
+    privacy_text: "Rendez-vous sur {export_link} pour obtenir une copie de vos données \
+        personnelles.\n\
+        Rendez-vous sur {erasure_link} pour vous désinscrire et effacer toutes vos \
+        données personnelles.\n\
+        Si vous n'avez pas demandé cet email, vous pouvez l'ignorer.",
+    privacy_html: "Cliquez <a href=\"{export_link}\">ici</a> pour obtenir une copie de \
+        vos données personnelles.<br /> \
+        Cliquez <a href=\"{erasure_link}\">ici</a> pour vous désinscrire et effacer \
+        toutes vos données personnelles.<br /> \
+        Si vous n'avez pas demandé cet email, vous pouvez l'ignorer.",
+    export_subject: "Une copie de vos données personnelles",
+    export_text: "Voici toutes les données personnelles que nous conservons sur vous :\n\n\
+        {export}",
+    export_html: "Voici toutes les données personnelles que nous conservons sur vous :\
+        <pre>{export}</pre>",
+    erasure_title: "Effacer vos données personnelles",
+    erasure_prompt: "Vous serez désinscrit, et toutes vos données personnelles seront \
+        effacées. Cette action est irréversible.",
+    erasure_button: "Effacer mes données",
+};
+
+const DE: Catalog = Catalog {
+    confirmation_subject: "Willkommen!",
+    confirmation_text: "Willkommen bei unserem Newsletter!\n\
+        Besuche {link}, um dein Abonnement zu bestätigen.",
+    confirmation_html: "Willkommen bei unserem Newsletter!<br /> \
+        Klicke <a href=\"{link}\">hier</a>, um dein Abonnement zu bestätigen.",
+    privacy_subject: "Deine personenbezogenen Daten",
+    privacy_text: "Besuche {export_link}, um eine Kopie deiner Daten zu erhalten.\n\
+        Besuche {erasure_link}, um dich abzumelden und alle deine Daten zu löschen.\n\
+        Falls du diese E-Mail nicht angefordert hast, kannst du sie ignorieren.",
+    privacy_html: "Klicke <a href=\"{export_link}\">hier</a>, um eine Kopie deiner \
+        Daten zu erhalten.<br /> \
+        Klicke <a href=\"{erasure_link}\">hier</a>, um dich abzumelden und alle deine \
+        Daten zu löschen.<br /> \
+        Falls du diese E-Mail nicht angefordert hast, kannst du sie ignorieren.",
+    export_subject: "Eine Kopie deiner personenbezogenen Daten",
+    export_text: "Das sind alle personenbezogenen Daten, die wir über dich speichern:\n\n\
+        {export}",
+    export_html: "Das sind alle personenbezogenen Daten, die wir über dich speichern:\
+        <pre>{export}</pre>",
+    erasure_title: "Deine personenbezogenen Daten löschen",
+    erasure_prompt: "Du wirst abgemeldet und alle deine Daten werden gelöscht. Das \
+        kann nicht rückgängig gemacht werden.",
+    erasure_button: "Meine Daten löschen",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use actix_web::test::TestRequest;
+    use claim::{assert_none, assert_some_eq};
+
+    #[test]
+    fn regional_variants_get_the_locale_of_their_language() {
+        assert_some_eq!(Locale::parse("es-AR"), Locale::Es);
+        assert_some_eq!(Locale::parse("de_AT"), Locale::De);
+        assert_some_eq!(Locale::parse("FR"), Locale::Fr);
+    }
+
+    #[test]
+    fn unsupported_languages_are_rejected() {
+        assert_none!(Locale::parse("ja"));
+        assert_none!(Locale::parse(""));
+    }
+
+    #[test]
+    fn the_requested_locale_wins_over_the_header() {
+        let req = TestRequest::default()
+            .insert_header(("Accept-Language", "fr"))
+            .to_http_request();
+        let accepted = Locale::from_accept_language(&req);
+        assert_eq!(Locale::negotiate(Some("de"), accepted), Locale::De);
+    }
+
+    #[test]
+    fn the_preferred_supported_language_of_the_header_is_used() {
+        let req = TestRequest::default()
+            .insert_header(("Accept-Language", "ja;q=1.0, es-ES;q=0.8, fr;q=0.5"))
+            .to_http_request();
+        let accepted = Locale::from_accept_language(&req);
+        assert_eq!(Locale::negotiate(Some("ja"), accepted), Locale::Es);
+    }
+
+    #[test]
+    fn the_default_locale_is_used_as_a_fallback() {
+        let req = TestRequest::default().to_http_request();
+        let accepted = Locale::from_accept_language(&req);
+        assert_eq!(Locale::negotiate(None, accepted), Locale::En);
+    }
+
+    #[test]
+    fn catalogs_include_the_confirmation_link() {
+        for locale in Locale::ALL {
+            let catalog = locale.catalog();
+            assert!(catalog
+                .confirmation_text("https://link")
+                .contains("https://link"));
+            assert!(catalog
+                .confirmation_html("https://link")
+                .contains("https://link"));
+            let privacy = catalog.privacy_text("https://export", "https://erase");
+            assert!(privacy.contains("https://export") && privacy.contains("https://erase"));
+            let privacy = catalog.privacy_html("https://export", "https://erase");
+            assert!(privacy.contains("https://export") && privacy.contains("https://erase"));
+        }
+    }
+
+    #[test]
+    fn exports_are_escaped_in_html_emails() {
+        let html = Locale::En
+            .catalog()
+            .export_html(r#"{"name": "<Jane & John>"}"#);
+        assert!(html.contains(r#"{"name": "&lt;Jane &amp; John&gt;"}"#));
+    }
+}
@@ -0,0 +1,310 @@
+//! Module that includes the background worker that delivers newsletter issues.
+//!
+//! # Description
+//!
+//! Publishing a newsletter issue only queues one delivery per recipient in the
+//! `issue_deliveries` table. The worker defined in this module dequeues those
+//! deliveries one by one, sends the emails and records the outcome of each of
+//! them, so the progress of a send can be followed while it is running.
+
+use crate::configuration::Settings;
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use crate::routes::is_suppressed;
+use crate::startup::get_connection_pool;
+use crate::telemetry::pii;
+use crate::EmailClient;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+/// Result of a single iteration of the worker.
+pub enum ExecutionOutcome {
+    /// A queued delivery was processed.
+    TaskCompleted,
+    /// There was nothing to deliver.
+    EmptyQueue,
+}
+
+/// Final status of a delivery.
+enum DeliveryOutcome {
+    Sent { provider_message_id: Option<String> },
+    Failed { reason: String },
+    Skipped { reason: String },
+}
+
+impl DeliveryOutcome {
+    fn status(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent { .. } => "sent",
+            DeliveryOutcome::Failed { .. } => "failed",
+            DeliveryOutcome::Skipped { .. } => "skipped",
+        }
+    }
+}
+
+/// Run the issue delivery worker until `shutdown` is signalled, and then close its
+/// DB pool.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    let email_normalization = configuration.email_verification.normalization();
+
+    worker_loop(
+        &connection_pool,
+        &email_client,
+        email_normalization,
+        shutdown,
+    )
+    .await;
+    connection_pool.close().await;
+
+    Ok(())
+}
+
+/// Deliver the queued newsletter issues until `shutdown` is signalled.
+///
+/// # Description
+///
+/// The shutdown signal is only checked between deliveries, so the email that is
+/// being sent when it's signalled is always delivered and recorded. The worker also
+/// stops if the sender of the signal is dropped.
+pub async fn worker_loop(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_normalization: EmailNormalization,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    while !*shutdown.borrow() {
+        let delay = match try_execute_task(pool, email_client, email_normalization).await {
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+            Ok(ExecutionOutcome::EmptyQueue) => Duration::from_secs(10),
+            Err(_) => Duration::from_secs(1),
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            changed = shutdown.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    tracing::info!("The issue delivery worker has stopped");
+}
+
+/// Deliver a single queued newsletter issue, if any.
+///
+/// # Description
+///
+/// The delivery row is locked while the email is being sent, so several workers
+/// can run concurrently without sending the same email twice. Errors returned by
+/// the email provider don't make this function fail: they are recorded in the
+/// delivery row along with the reason of the failure.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_normalization: EmailNormalization,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let task = dequeue_task(pool)
+        .await
+        .context("Failed to dequeue a delivery task.")?;
+    let (transaction, issue_id, email) = match task {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+
+    Span::current()
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(pii(&email)));
+
+    let outcome = match SubscriberEmail::parse_with(email.clone(), email_normalization) {
+        Ok(recipient) => deliver_issue(pool, email_client, issue_id, &recipient).await?,
+        Err(error) => {
+            tracing::warn!(
+                error.cause_chain = ?error,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
+            );
+            DeliveryOutcome::Failed { reason: error }
+        }
+    };
+
+    record_delivery_outcome(transaction, issue_id, &email, &outcome)
+        .await
+        .context("Failed to record the outcome of a delivery.")?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn deliver_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    issue_id: Uuid,
+    recipient: &SubscriberEmail,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    if is_suppressed(pool, recipient)
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!(
+            subscriber_email = %pii(recipient),
+            "Skipping a newsletter issue to a suppressed address",
+        );
+        return Ok(DeliveryOutcome::Skipped {
+            reason: "The address is suppressed.".into(),
+        });
+    }
+
+    let issue = get_issue(pool, issue_id, recipient)
+        .await
+        .context("Failed to fetch the newsletter issue.")?;
+
+    let outcome = match email_client
+        .send_email(
+            recipient,
+            &issue.title,
+            &issue.html_content,
+            &issue.text_content,
+        )
+        .await
+    {
+        Ok(provider_message_id) => DeliveryOutcome::Sent {
+            provider_message_id,
+        },
+        Err(error) => {
+            tracing::error!(
+                error.cause_chain = ?error,
+                error.message = %error,
+                "Failed to deliver issue to a confirmed subscriber.",
+            );
+            DeliveryOutcome::Failed {
+                reason: error.to_string(),
+            }
+        }
+    };
+
+    Ok(outcome)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, String)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, subscriber_email
+        FROM issue_deliveries
+        WHERE status = 'queued'
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+
+    if let Some(r) = r {
+        Ok(Some((
+            transaction,
+            r.newsletter_issue_id,
+            r.subscriber_email,
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_delivery_outcome(
+    mut transaction: PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    outcome: &DeliveryOutcome,
+) -> Result<(), sqlx::Error> {
+    let (provider_message_id, failure_reason) = match outcome {
+        DeliveryOutcome::Sent {
+            provider_message_id,
+        } => (provider_message_id.as_deref(), None),
+        DeliveryOutcome::Failed { reason } | DeliveryOutcome::Skipped { reason } => {
+            (None, Some(reason.as_str()))
+        }
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $3, provider_message_id = $4, failure_reason = $5, updated_at = $6
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        outcome.status(),
+        provider_message_id,
+        failure_reason,
+        Utc::now(),
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Get an issue in the locale of its recipient.
+///
+/// # Description
+///
+/// When the issue has no variant for the locale of the recipient, the default
+/// title and content are used.
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    recipient: &SubscriberEmail,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            COALESCE(v.title, i.title) AS "title!",
+            COALESCE(v.text_content, i.text_content) AS "text_content!",
+            COALESCE(v.html_content, i.html_content) AS "html_content!"
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_issue_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id
+            AND v.locale = (
+                SELECT locale FROM subscriptions WHERE normalized_email = $2
+            )
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id,
+        recipient.normalized(),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(issue)
+}
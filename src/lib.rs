@@ -1,30 +1,56 @@
+pub mod admin;
+pub mod authentication;
+pub mod bot_protection;
 pub mod configuration;
+pub mod consent;
 pub mod email_client;
+pub mod email_verifier;
+pub mod i18n;
+pub mod issue_delivery_worker;
+pub mod metrics;
+pub mod signed_token;
 pub mod startup;
 pub mod telemetry;
+pub mod tls;
 
 mod routes {
+    mod consent_events;
+    mod errors;
     mod health_check;
+    mod issues;
+    mod metrics;
     mod newsletters;
+    mod subscribe_form;
     mod subscriptions;
     mod subscriptions_confirm;
+    mod subscriptions_privacy;
+    mod suppressions;
 
+    pub use consent_events::*;
+    pub use errors::*;
     pub use health_check::*;
+    pub use issues::*;
+    pub use metrics::*;
     pub use newsletters::*;
+    pub use subscribe_form::*;
     pub use subscriptions::error_chain_fmt;
     pub use subscriptions::*;
     pub use subscriptions_confirm::*;
+    pub use subscriptions_privacy::*;
+    pub use suppressions::*;
 }
 
 mod domain {
     mod new_subscriber;
     mod subscriber_email;
     mod subscriber_name;
+    mod subscription_token;
 
     pub use new_subscriber::NewSubscriber;
-    pub use subscriber_email::SubscriberEmail;
+    pub use subscriber_email::{EmailNormalization, SubscriberEmail};
     pub use subscriber_name::SubscriberName;
+    pub use subscription_token::{SubscriptionToken, TokenGenerator};
 }
 
-pub use domain::NewSubscriber;
+pub use domain::{EmailNormalization, NewSubscriber, SubscriberEmail};
 pub use email_client::EmailClient;
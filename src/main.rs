@@ -1,18 +1,120 @@
+use anyhow::Context;
 use newsletter::configuration::get_configuration;
+use newsletter::issue_delivery_worker::run_worker_until_stopped;
 use newsletter::startup::Application;
-use newsletter::telemetry::{get_subscriber, init_subscriber};
+use newsletter::telemetry::{get_subscriber, init_subscriber, set_pii_redaction};
+use std::fmt::{Debug, Display};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinError;
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> anyhow::Result<()> {
+    // Load the configuration settings from a YAML file.
+    let configuration = get_configuration().context("Failed to read configuration.")?;
+
     // Init the tracing subsystem.
-    let subscriber = get_subscriber("newsletter".into(), "info".into(), std::io::stdout);
+    let tracer = configuration.opentelemetry.tracer("newsletter")?;
+    let subscriber = get_subscriber(
+        "newsletter".into(),
+        "info".into(),
+        std::io::stdout,
+        configuration.log.format,
+        tracer,
+    );
     init_subscriber(subscriber);
+    set_pii_redaction(
+        configuration.log.redact_pii,
+        configuration.log.redaction_key.as_ref(),
+    );
+    let application = Application::build(configuration.clone()).await?;
 
-    // Load the configuration settings from a YAML file.
-    let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(configuration).await?;
+    let server_handle = application.handle();
+    let shutdown_timeout = configuration.application.shutdown_timeout();
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
 
-    application.run_until_stopped().await?;
+    // Run the API and the background delivery of newsletter issues side by side.
+    let mut application_task = tokio::spawn(application.run_until_stopped());
+    let mut worker_task = tokio::spawn(run_worker_until_stopped(configuration, shutdown_receiver));
+
+    tokio::select! {
+        o = &mut application_task => report_exit("API", o),
+        o = &mut worker_task => report_exit("Background worker", o),
+        _ = shutdown_signal() => {
+            tracing::info!("Shutting down, waiting for the in-flight work to finish");
+            // The receiver is only dropped if the worker has already exited.
+            let _ = shutdown_sender.send(true);
+            let drain = async {
+                server_handle.stop(true).await;
+                report_exit("API", application_task.await);
+                report_exit("Background worker", worker_task.await);
+            };
+            if tokio::time::timeout(shutdown_timeout, drain).await.is_err() {
+                tracing::warn!(
+                    "The in-flight work didn't finish within {} seconds",
+                    shutdown_timeout.as_secs()
+                );
+            }
+        }
+    };
+
+    // Export the spans that are still buffered.
+    opentelemetry::global::shutdown_tracer_provider();
 
     Ok(())
 }
+
+/// Wait for SIGTERM or, mainly when running locally, Ctrl-C.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error.message = %e, "Failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error.message = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
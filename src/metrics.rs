@@ -0,0 +1,171 @@
+//! Module that includes the Prometheus metrics of the application.
+//!
+//! # Description
+//!
+//! Metrics are registered in a registry that is shared by the whole process, so the
+//! API and the background delivery worker report through the same `/metrics`
+//! endpoint. The metrics are:
+//! - `http_requests_total` and `http_request_duration_seconds`, per method, route
+//!   pattern and status. Route patterns, e.g. `/admin/issues/{issue_id}`, and
+//!   grouping the non-standard methods as `other` keep the number of series bounded.
+//! - `newsletter_subscriptions_total` and `newsletter_confirmations_total`.
+//! - `email_sends_total`, per outcome, and `email_send_duration_seconds`.
+//! - `db_pool_connections`, `db_pool_idle_connections` and
+//!   `db_pool_max_connections`, which are updated on every scrape.
+
+use actix_web::http::{Method, StatusCode};
+use once_cell::sync::Lazy;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::time::Duration;
+
+struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    subscriptions: IntCounter,
+    confirmations: IntCounter,
+    email_sends: IntCounterVec,
+    email_send_duration: Histogram,
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+    db_pool_max_connections: IntGauge,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| {
+    let metrics = Metrics {
+        registry: Registry::new(),
+        http_requests: IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of HTTP requests."),
+            &["method", "route", "status"],
+        )
+        .unwrap(),
+        http_request_duration: HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests.",
+            ),
+            &["method", "route"],
+        )
+        .unwrap(),
+        subscriptions: IntCounter::new(
+            "newsletter_subscriptions_total",
+            "Number of subscription requests that were accepted.",
+        )
+        .unwrap(),
+        confirmations: IntCounter::new(
+            "newsletter_confirmations_total",
+            "Number of subscriptions that were confirmed.",
+        )
+        .unwrap(),
+        email_sends: IntCounterVec::new(
+            Opts::new(
+                "email_sends_total",
+                "Number of emails sent to the provider.",
+            ),
+            &["outcome"],
+        )
+        .unwrap(),
+        email_send_duration: Histogram::with_opts(HistogramOpts::new(
+            "email_send_duration_seconds",
+            "Time taken by the email provider to handle an email.",
+        ))
+        .unwrap(),
+        db_pool_connections: IntGauge::new(
+            "db_pool_connections",
+            "Number of connections of the DB pool.",
+        )
+        .unwrap(),
+        db_pool_idle_connections: IntGauge::new(
+            "db_pool_idle_connections",
+            "Number of idle connections of the DB pool.",
+        )
+        .unwrap(),
+        db_pool_max_connections: IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum number of connections of the DB pool.",
+        )
+        .unwrap(),
+    };
+
+    let collectors: Vec<Box<dyn Collector>> = vec![
+        Box::new(metrics.http_requests.clone()),
+        Box::new(metrics.http_request_duration.clone()),
+        Box::new(metrics.subscriptions.clone()),
+        Box::new(metrics.confirmations.clone()),
+        Box::new(metrics.email_sends.clone()),
+        Box::new(metrics.email_send_duration.clone()),
+        Box::new(metrics.db_pool_connections.clone()),
+        Box::new(metrics.db_pool_idle_connections.clone()),
+        Box::new(metrics.db_pool_max_connections.clone()),
+    ];
+    for collector in collectors {
+        metrics.registry.register(collector).unwrap();
+    }
+
+    metrics
+});
+
+/// Record a handled HTTP request. Requests that didn't match any route are
+/// recorded with the `unmatched` route, and the ones with a non-standard method with
+/// the `other` method, so arbitrary requests don't add new series.
+pub fn record_request(method: &Method, route: Option<&str>, status: StatusCode, elapsed: Duration) {
+    let method = match *method {
+        Method::GET
+        | Method::HEAD
+        | Method::POST
+        | Method::PUT
+        | Method::DELETE
+        | Method::CONNECT
+        | Method::OPTIONS
+        | Method::TRACE
+        | Method::PATCH => method.as_str(),
+        _ => "other",
+    };
+    let route = route.unwrap_or("unmatched");
+    METRICS
+        .http_requests
+        .with_label_values(&[method, route, status.as_str()])
+        .inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&[method, route])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Record an accepted subscription request.
+pub fn record_subscription() {
+    METRICS.subscriptions.inc();
+}
+
+/// Record a confirmed subscription.
+pub fn record_confirmation() {
+    METRICS.confirmations.inc();
+}
+
+/// Record an email sent to the provider, along with whether the provider accepted
+/// it.
+pub fn record_email_send(succeeded: bool, elapsed: Duration) {
+    let outcome = if succeeded { "success" } else { "failure" };
+    METRICS.email_sends.with_label_values(&[outcome]).inc();
+    METRICS.email_send_duration.observe(elapsed.as_secs_f64());
+}
+
+/// Record the usage of the DB pool.
+pub fn record_db_pool(pool: &PgPool, max_connections: u32) {
+    METRICS.db_pool_connections.set(pool.size().into());
+    METRICS.db_pool_idle_connections.set(pool.num_idle() as i64);
+    METRICS.db_pool_max_connections.set(max_connections.into());
+}
+
+/// Encode the metrics in the Prometheus text format.
+pub fn encode() -> Result<Vec<u8>, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer)?;
+
+    Ok(buffer)
+}
@@ -0,0 +1,104 @@
+//! Module that includes the endpoint to export the consent audit log.
+
+use crate::authentication::AdminUser;
+use crate::consent::{get_consent_events, ConsentEvent};
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use crate::routes::{error_chain_fmt, json_error_response, ErrorBody};
+use crate::telemetry::pii;
+use actix_web::http::StatusCode;
+use actix_web::{post, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// Consent audit log of a subscriber.
+#[derive(serde::Serialize)]
+struct ConsentLog {
+    subscriber_email: String,
+    events: Vec<ConsentEvent>,
+}
+
+/// Body of the requests to export the consent audit log of a subscriber.
+#[derive(serde::Deserialize)]
+pub struct ConsentLogRequest {
+    subscriber_email: String,
+}
+
+/// Post endpoint that exports the consent audit log of a subscriber.
+///
+/// # Description
+///
+/// The email is sent in the body rather than in the path, so it isn't recorded in
+/// the target of the request spans. The subscriber is looked up using the
+/// normalized form of their email, see [EmailNormalization].
+#[tracing::instrument(
+    name = "Export the consent events of a subscriber",
+    skip_all,
+    fields(subscriber_email = %pii(&body.subscriber_email))
+)]
+#[post("/admin/consent-events")]
+pub async fn export_consent_events(
+    _admin: AdminUser,
+    body: web::Json<ConsentLogRequest>,
+    pool: web::Data<PgPool>,
+    email_normalization: web::Data<EmailNormalization>,
+) -> Result<HttpResponse, ConsentLogError> {
+    let email =
+        SubscriberEmail::parse_with(body.into_inner().subscriber_email, **email_normalization)
+            .map_err(ConsentLogError::ValidationError)?;
+
+    let subscriber = sqlx::query!(
+        "SELECT id, email FROM subscriptions WHERE normalized_email = $1",
+        email.normalized(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to query to the database.")?
+    .ok_or(ConsentLogError::UnknownSubscriber)?;
+
+    let events = get_consent_events(pool.get_ref(), subscriber.id)
+        .await
+        .context("Failed to fetch the consent events of the subscriber.")?;
+
+    Ok(HttpResponse::Ok().json(ConsentLog {
+        subscriber_email: subscriber.email,
+        events,
+    }))
+}
+
+#[derive(thiserror::Error)]
+pub enum ConsentLogError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The email doesn't belong to any subscriber.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConsentLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConsentLogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConsentLogError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ConsentLogError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            ConsentLogError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            ConsentLogError::ValidationError(e) => {
+                ErrorBody::new("Invalid subscriber email.", vec![e.clone()])
+            }
+            ConsentLogError::UnknownSubscriber => ErrorBody::new(self.to_string(), Vec::new()),
+            ConsentLogError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
+}
@@ -0,0 +1,59 @@
+//! Module that includes the common format of the error responses.
+//!
+//! # Description
+//!
+//! Every endpoint of the newsletter application reports errors to clients using a
+//! JSON body like this one:
+//!
+//! ```json
+//! { "error": "Invalid newsletter issue.", "details": ["The title is empty."] }
+//! ```
+//!
+//! Errors raised while extracting the request's data (malformed JSON, missing
+//! form fields, etc.) follow the same format using [extractor_error].
+
+use actix_web::{error::InternalError, http::StatusCode, HttpResponse, ResponseError};
+
+/// Body of an error response.
+#[derive(serde::Serialize, Debug)]
+pub struct ErrorBody {
+    error: String,
+    details: Vec<String>,
+}
+
+impl ErrorBody {
+    pub fn new(error: impl Into<String>, details: Vec<String>) -> Self {
+        Self {
+            error: error.into(),
+            details,
+        }
+    }
+
+    /// Body for errors whose details must not be disclosed to clients.
+    pub fn internal() -> Self {
+        Self::new("An internal error occurred.", Vec::new())
+    }
+}
+
+/// Build an error response with a JSON [ErrorBody].
+pub fn json_error_response(status: StatusCode, body: ErrorBody) -> HttpResponse {
+    HttpResponse::build(status).json(body)
+}
+
+/// Error handler for the extractors (`Json`, `Form`, `Query` and `Path`).
+///
+/// # Description
+///
+/// The status code of the original error is kept, only the body of the response
+/// is replaced by an [ErrorBody].
+pub fn extractor_error<E>(err: E) -> actix_web::Error
+where
+    E: ResponseError + 'static,
+{
+    let response = json_error_response(
+        err.status_code(),
+        ErrorBody::new("Invalid request.", vec![err.to_string()]),
+    );
+
+    InternalError::from_response(err, response).into()
+}
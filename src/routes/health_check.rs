@@ -1,14 +1,129 @@
-//! Module that includes a health check endpoint.
+//! Module that includes the health check endpoints.
 
-use actix_web::{get, HttpResponse, Responder};
+use crate::email_client::ProviderStatus;
+use crate::startup::MaxDbConnections;
+use crate::EmailClient;
+use actix_web::{get, web, HttpResponse, Responder};
+use sqlx::{Connection, PgPool};
 
 /// Endpoint that tells a client about the server health status.
 ///
 /// # Description
 ///
 /// This endpoint can be used by clients to check whether the server is working as
-/// expected, or it's blocked and unresponsive for some reason.
+/// expected, or it's blocked and unresponsive for some reason. It doesn't check any
+/// dependency of the server, so it's suitable as a liveness probe.
 #[get("/health_check")]
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().finish()
 }
+
+/// Breakdown of the readiness of the server.
+#[derive(serde::Serialize)]
+struct Readiness {
+    status: &'static str,
+    checks: ReadinessChecks,
+}
+
+#[derive(serde::Serialize)]
+struct ReadinessChecks {
+    database: DatabaseCheck,
+    email_provider: EmailProviderCheck,
+}
+
+#[derive(serde::Serialize)]
+struct DatabaseCheck {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    pool: PoolUsage,
+}
+
+/// Usage of the connection pool. The saturation is the fraction of the maximum
+/// number of connections that are in use.
+#[derive(serde::Serialize)]
+struct PoolUsage {
+    size: u32,
+    idle: usize,
+    max_connections: u32,
+    saturation: f64,
+}
+
+#[derive(serde::Serialize)]
+struct EmailProviderCheck {
+    status: &'static str,
+    #[serde(flatten)]
+    provider_status: ProviderStatus,
+}
+
+/// Endpoint that tells whether the server is ready to handle requests.
+///
+/// # Description
+///
+/// The response includes a breakdown of the checks of each dependency:
+/// - The DB is pinged, and the usage of the connection pool is reported.
+/// - The email provider reports when it last accepted and last rejected an email.
+///   When the latest email was rejected, it's reported as `degraded`, but that
+///   doesn't make the server unready: taking the server out of rotation wouldn't fix
+///   the provider, and would keep the server from noticing when it recovers.
+///
+/// The server is ready, and a 200 is returned, when all the dependencies are
+/// healthy. Otherwise a 503 is returned.
+#[tracing::instrument(name = "Check readiness", skip_all)]
+#[get("/health/ready")]
+pub async fn health_ready(
+    pool: web::Data<PgPool>,
+    max_db_connections: web::Data<MaxDbConnections>,
+    email_client: web::Data<EmailClient>,
+) -> impl Responder {
+    let database = check_database(&pool, max_db_connections.0).await;
+    let provider_status = email_client.provider_status();
+    let email_provider = EmailProviderCheck {
+        status: match (provider_status.last_success, provider_status.is_failing()) {
+            (_, true) => "degraded",
+            (None, false) => "unknown",
+            (Some(_), false) => "healthy",
+        },
+        provider_status,
+    };
+
+    let is_ready = database.error.is_none();
+    let readiness = Readiness {
+        status: if is_ready { "ready" } else { "not_ready" },
+        checks: ReadinessChecks {
+            database,
+            email_provider,
+        },
+    };
+
+    if is_ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+async fn check_database(pool: &PgPool, max_connections: u32) -> DatabaseCheck {
+    let ping = match pool.acquire().await {
+        Ok(mut connection) => connection.ping().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &ping {
+        tracing::warn!(error.message = %e, "The DB is not reachable");
+    }
+
+    let size = pool.size();
+    let idle = pool.num_idle();
+    let in_use = size.saturating_sub(idle as u32);
+
+    DatabaseCheck {
+        status: if ping.is_ok() { "healthy" } else { "unhealthy" },
+        error: ping.err().map(|e| e.to_string()),
+        pool: PoolUsage {
+            size,
+            idle,
+            max_connections,
+            saturation: f64::from(in_use) / f64::from(max_connections),
+        },
+    }
+}
@@ -0,0 +1,144 @@
+//! Module that includes the endpoints to follow the delivery of newsletter issues.
+
+use crate::authentication::AdminUser;
+use crate::routes::{error_chain_fmt, json_error_response, ErrorBody};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Summary of the delivery of a newsletter issue.
+#[derive(serde::Serialize)]
+pub struct DeliveryReport {
+    issue_id: Uuid,
+    title: String,
+    total: i64,
+    queued: i64,
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+    failures: Vec<DeliveryFailure>,
+}
+
+/// A delivery that didn't succeed, along with its reason.
+#[derive(serde::Serialize)]
+pub struct DeliveryFailure {
+    subscriber_email: String,
+    status: String,
+    reason: Option<String>,
+}
+
+/// Get endpoint that reports the delivery progress of a newsletter issue.
+///
+/// # Description
+///
+/// The report includes the count of deliveries per status, and the list of the
+/// deliveries that failed or were skipped along with their reason. As deliveries
+/// are processed in the background, the report can be requested while the issue
+/// is still being sent to follow its progress.
+#[tracing::instrument(name = "Report the delivery of an issue", skip(_admin, pool))]
+#[get("/admin/issues/{issue_id}/report")]
+pub async fn issue_report(
+    _admin: AdminUser,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueReportError> {
+    let issue_id = issue_id.into_inner();
+    let report = get_delivery_report(&pool, issue_id)
+        .await
+        .context("Failed to build the delivery report of the issue.")?
+        .ok_or(IssueReportError::UnknownIssue(issue_id))?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[tracing::instrument(name = "Get the delivery report of an issue", skip(pool))]
+async fn get_delivery_report(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<DeliveryReport>, sqlx::Error> {
+    let issue = sqlx::query!(
+        "SELECT title FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let title = match issue {
+        Some(issue) => issue.title,
+        None => return Ok(None),
+    };
+
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "total!",
+            COUNT(*) FILTER (WHERE status = 'queued') AS "queued!",
+            COUNT(*) FILTER (WHERE status = 'sent') AS "sent!",
+            COUNT(*) FILTER (WHERE status = 'failed') AS "failed!",
+            COUNT(*) FILTER (WHERE status = 'skipped') AS "skipped!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let failures = sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT subscriber_email, status, failure_reason AS reason
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1 AND status IN ('failed', 'skipped')
+        ORDER BY updated_at
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(DeliveryReport {
+        issue_id,
+        title,
+        total: counts.total,
+        queued: counts.queued,
+        sent: counts.sent,
+        failed: counts.failed,
+        skipped: counts.skipped,
+        failures,
+    }))
+}
+
+#[derive(thiserror::Error)]
+pub enum IssueReportError {
+    #[error("There is no newsletter issue with ID {0}.")]
+    UnknownIssue(Uuid),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IssueReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IssueReportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IssueReportError::UnknownIssue(_) => StatusCode::NOT_FOUND,
+            IssueReportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            IssueReportError::UnknownIssue(_) => ErrorBody::new(self.to_string(), Vec::new()),
+            IssueReportError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
+}
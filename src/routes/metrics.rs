@@ -0,0 +1,24 @@
+//! Module that includes the endpoint that exposes the metrics of the application.
+
+use crate::metrics::{encode, record_db_pool};
+use crate::startup::MaxDbConnections;
+use actix_web::{get, web, HttpResponse};
+use prometheus::TEXT_FORMAT;
+use sqlx::PgPool;
+
+/// Get endpoint that exposes the metrics in the Prometheus text format.
+#[get("/metrics")]
+pub async fn metrics(
+    pool: web::Data<PgPool>,
+    max_db_connections: web::Data<MaxDbConnections>,
+) -> HttpResponse {
+    record_db_pool(&pool, max_db_connections.0);
+
+    match encode() {
+        Ok(body) => HttpResponse::Ok().content_type(TEXT_FORMAT).body(body),
+        Err(e) => {
+            tracing::error!(error.message = %e, "Failed to encode the metrics");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,18 +1,32 @@
 //! src/routes/newsletter.rs
 
-use crate::{domain::SubscriberEmail, routes::error_chain_fmt, EmailClient};
+use crate::i18n::Locale;
+use crate::routes::{error_chain_fmt, json_error_response, ErrorBody};
 use actix_web::{http::StatusCode, post, web, HttpResponse, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashSet};
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
-}
+/// Maximum length of the title of an issue, in graphemes.
+const MAX_TITLE_LENGTH: usize = 256;
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
     content: Content,
+    /// Translations of the issue, by locale. Subscribers whose locale has no
+    /// variant get the default title and content.
+    #[serde(default)]
+    variants: BTreeMap<String, IssueVariant>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct IssueVariant {
+    title: String,
+    content: Content,
 }
 
 #[derive(serde::Deserialize)]
@@ -21,8 +35,63 @@ pub struct Content {
     text: String,
 }
 
+impl BodyData {
+    /// Check the issue's content before sending it to anyone.
+    ///
+    /// # Description
+    ///
+    /// All the problems that are found are reported at once.
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        validate_content(&self.title, &self.content, "", &mut problems);
+
+        let mut locales = HashSet::new();
+        for (tag, variant) in &self.variants {
+            match Locale::parse(tag) {
+                Some(locale) if !locales.insert(locale) => {
+                    problems.push(format!("There is more than one {locale} variant."))
+                }
+                Some(_) => {}
+                None => problems.push(format!("The locale {tag:?} is not supported.")),
+            }
+            let prefix = format!("The {tag} variant: ");
+            validate_content(&variant.title, &variant.content, &prefix, &mut problems);
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Check the title and content of an issue, or of one of its variants.
+fn validate_content(title: &str, content: &Content, prefix: &str, problems: &mut Vec<String>) {
+    if title.trim().is_empty() {
+        problems.push(format!("{prefix}The title is empty."));
+    } else if title.graphemes(true).count() > MAX_TITLE_LENGTH {
+        problems.push(format!(
+            "{prefix}The title is longer than {MAX_TITLE_LENGTH} characters."
+        ));
+    }
+    if content.html.trim().is_empty() {
+        problems.push(format!("{prefix}The HTML content is empty."));
+    }
+    if content.text.trim().is_empty() {
+        problems.push(format!("{prefix}The plain text content is empty."));
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PublishResponse {
+    issue_id: Uuid,
+}
+
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("Invalid newsletter issue: {}", .0.join(" "))]
+    ValidationError(Vec<String>),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -36,67 +105,151 @@ impl std::fmt::Debug for PublishError {
 impl ResponseError for PublishError {
     fn status_code(&self) -> StatusCode {
         match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
             PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            PublishError::ValidationError(problems) => {
+                ErrorBody::new("Invalid newsletter issue.", problems.clone())
+            }
+            PublishError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
 }
 
-// Dummy implementation
+/// Post endpoint to publish a newsletter issue.
+///
+/// # Description
+///
+/// The issue is stored in the DB, along with its variants for other locales, and a
+/// delivery is queued for every confirmed subscriber. The actual emails are sent in
+/// the background by the issue delivery worker, so the progress of the send can be
+/// followed using the ID of the issue that is returned in the response.
+#[tracing::instrument(name = "Publish a newsletter issue", skip(body, pool))]
 #[post("/newsletters")]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
 ) -> Result<HttpResponse, PublishError> {
-    let subscribers = get_confirmed_subscribers(&pool).await?;
-
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                    "Skipping a confirmed subscriber, \
-                    Their stored contact details are invalid",
-                );
-            }
-        }
+    body.validate().map_err(PublishError::ValidationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let issue_id = insert_newsletter_issue(&mut transaction, &body)
+        .await
+        .context("Failed to store newsletter issue details.")?;
+
+    insert_issue_variants(&mut transaction, issue_id, &body.variants)
+        .await
+        .context("Failed to store the variants of the newsletter issue.")?;
+
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+
+    Ok(HttpResponse::Ok().json(PublishResponse { issue_id }))
+}
+
+#[tracing::instrument(name = "Save newsletter issue details", skip(transaction, body))]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &BodyData,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html,
+        Utc::now(),
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(name = "Save newsletter issue variants", skip(transaction, variants))]
+async fn insert_issue_variants(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    variants: &BTreeMap<String, IssueVariant>,
+) -> Result<(), sqlx::Error> {
+    for (tag, variant) in variants {
+        // Variants were validated, so all of them have a supported locale.
+        let locale = Locale::parse(tag).unwrap_or_default();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_variants (
+                newsletter_issue_id,
+                locale,
+                title,
+                text_content,
+                html_content
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            newsletter_issue_id,
+            locale.as_str(),
+            variant.title,
+            variant.content.text,
+            variant.content.html,
+        )
+        .execute(&mut *transaction)
+        .await?;
     }
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
+/// Queue a delivery of an issue for every confirmed subscriber.
+#[tracing::instrument(name = "Enqueue delivery tasks", skip(transaction))]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
         r#"
-        SELECT email
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            status,
+            updated_at
+        )
+        SELECT $1, email, 'queued', $2
         FROM subscriptions
         WHERE status = 'confirmed'
         "#,
+        newsletter_issue_id,
+        Utc::now(),
     )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
-
-    Ok(confirmed_subscribers)
+    .execute(transaction)
+    .await?;
+
+    Ok(())
 }
@@ -0,0 +1,47 @@
+//! Module that includes an embeddable signup form.
+//!
+//! # Description
+//!
+//! Third party pages can embed the HTML snippet served by this module to let their
+//! visitors subscribe to the newsletter. The snippet posts to the `/subscriptions`
+//! endpoint, so the origin of the page must be allowed in the CORS settings of the
+//! application.
+
+use crate::bot_protection::BotProtection;
+use crate::startup::ApplicationBaseUrl;
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+
+/// Get endpoint that serves the embeddable signup form.
+///
+/// # Description
+///
+/// The form includes a _honeypot_ field that is hidden to humans, and the signed
+/// time at which the form was rendered. Submissions that fill the honeypot, or that
+/// are sent too soon after rendering the form, are considered to come from bots and
+/// are discarded by the subscription endpoint. When captchas are enabled, the form
+/// also renders the widget of the captcha provider.
+#[get("/subscribe/form")]
+pub async fn subscribe_form(
+    base_url: web::Data<ApplicationBaseUrl>,
+    bot_protection: web::Data<BotProtection>,
+) -> HttpResponse {
+    let captcha = match bot_protection.captcha_widget() {
+        Some(widget) => format!(
+            "<script src=\"{}\" async defer></script>\n  \
+            <div class=\"{}\" data-sitekey=\"{}\"></div>",
+            widget.script_url, widget.class, widget.site_key
+        ),
+        None => String::new(),
+    };
+    let form = include_str!("subscribe_form.html")
+        .replace("{{base_url}}", &base_url.0)
+        .replace(
+            "{{rendered_at}}",
+            &bot_protection.render_token().unwrap_or_default(),
+        )
+        .replace("{{captcha}}", &captcha);
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(form)
+}
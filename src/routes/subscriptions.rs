@@ -5,16 +5,29 @@
 //! This module adds an endpoint that allows new clients to subscribe to the
 //! newsletter.
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::bot_protection::BotProtection;
+use crate::consent::{record_consent_event, ConsentContext, ConsentEventKind};
+use crate::domain::{
+    EmailNormalization, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionToken,
+    TokenGenerator,
+};
 use crate::email_client::EmailClient;
+use crate::email_verifier::EmailVerifier;
+use crate::i18n::Locale;
+use crate::metrics::record_subscription;
+use crate::routes::{is_suppressed, json_error_response, ErrorBody};
+use crate::signed_token::{TokenPurpose, TokenSigner};
 use crate::startup::ApplicationBaseUrl;
+use crate::telemetry::pii;
+use actix_web::dev::Payload;
 use actix_web::http::StatusCode;
-use actix_web::{post, web, HttpResponse, ResponseError};
+use actix_web::{post, web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
 use sqlx::{self, PgPool, Postgres, Transaction};
+use std::future::Future;
+use std::pin::Pin;
 use uuid::Uuid;
 
 /// Data that is included in the form that comes along the POST for the endpoint.
@@ -22,15 +35,94 @@ use uuid::Uuid;
 struct FormData {
     email: String,
     name: String,
+    /// Honeypot field of the signup form. Only bots fill it.
+    website: Option<String>,
+    /// Signed time at which the signup form was rendered.
+    rendered_at: Option<String>,
+    /// Response of the captcha widget, when captchas are enabled.
+    captcha_response: Option<String>,
+    /// Language in which the subscriber wants to get the emails.
+    locale: Option<String>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+impl FormData {
+    fn is_from_a_bot(&self) -> bool {
+        self.website
+            .as_deref()
+            .is_some_and(|w| !w.trim().is_empty())
+    }
+}
+
+/// Extractor for the body of a subscription request.
+///
+/// # Description
+///
+/// The subscription data is accepted either as a form-encoded body or as a JSON
+/// body, depending on the `Content-Type` of the request. Any other content type
+/// is rejected with a _415 Unsupported Media Type_.
+///
+/// The locale of the subscriber is negotiated here, as it may come from the
+/// `Accept-Language` header of the request. The source of the subscription, which
+/// is recorded in the consent audit log, also depends on the request.
+struct SubscriptionData {
+    form: FormData,
+    locale: Locale,
+    source: &'static str,
+}
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+impl FromRequest for SubscriptionData {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let accepted_locale = Locale::from_accept_language(req);
+        let build = move |form: FormData, api_source: &'static str| {
+            let locale = Locale::negotiate(form.locale.as_deref(), accepted_locale);
+            // Only the signup form includes the time at which it was rendered.
+            let source = match form.rendered_at {
+                Some(_) => "signup_form",
+                None => api_source,
+            };
+            Self {
+                form,
+                locale,
+                source,
+            }
+        };
+
+        match req.content_type() {
+            "application/json" => {
+                let json = web::Json::<FormData>::from_request(req, payload);
+                Box::pin(async move { Ok(build(json.await?.into_inner(), "json_api")) })
+            }
+            "application/x-www-form-urlencoded" => {
+                let form = web::Form::<FormData>::from_request(req, payload);
+                Box::pin(async move { Ok(build(form.await?.into_inner(), "form_api")) })
+            }
+            other => {
+                let error = SubscribeError::UnsupportedContentType(other.to_string());
+                Box::pin(async move { Err(error.into()) })
+            }
+        }
+    }
+}
+
+/// Body of the response to a successful subscription request.
+#[derive(serde::Serialize)]
+struct SubscribeResponse {
+    status: &'static str,
+}
+
+impl SubscriptionData {
+    /// Validate the data of the subscription, normalizing the email as configured.
+    fn parse(self, normalization: EmailNormalization) -> Result<NewSubscriber, String> {
+        let name = SubscriberName::parse(self.form.name)?;
+        let email = SubscriberEmail::parse_with(self.form.email, normalization)?;
+        Ok(NewSubscriber {
+            email,
+            name,
+            locale: self.locale,
+        })
     }
 }
 
@@ -43,35 +135,83 @@ impl TryFrom<FormData> for NewSubscriber {
 /// registered will be accepted. As of today, there's no endpoint to remove
 /// a subscribed client.
 ///
+/// The subscription data can be sent either as a form or as JSON, and the response
+/// includes a JSON body with the status of the subscription.
+///
+/// Requests go through the [BotProtection] checks before anything is stored, and
+/// the email of the subscriber is verified by the [EmailVerifier]. Every
+/// subscription attempt that passes them is recorded in the consent audit log.
+/// Submissions that look like coming from a bot get the same response as valid
+/// ones, except for failed captchas, which are rejected so humans can retry.
+///
 /// ## Arguments
 ///
 /// - An instance of the `struct` [FormData] that includes the data from the POST.
 /// - An instance of the DB's driver to issue the INSERT operation of the new subscription.
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip_all,
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name,
+        subscriber_email = %pii(&data.form.email),
+        subscriber_name = %pii(&data.form.name),
     )
 )]
 #[post("/subscriptions")]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    data: SubscriptionData,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    bot_protection: web::Data<BotProtection>,
+    email_verifier: web::Data<EmailVerifier>,
+    email_normalization: web::Data<EmailNormalization>,
+    consent: ConsentContext,
+    token_generator: web::Data<TokenGenerator>,
+    token_signer: Option<web::Data<TokenSigner>>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    // Pretend that everything went fine, so bots don't learn about the checks.
+    if data.form.is_from_a_bot() {
+        tracing::warn!("Discarding a subscription that filled the honeypot field");
+        return Ok(pending_confirmation());
+    }
+    if bot_protection.is_too_fast(data.form.rendered_at.as_deref()) {
+        tracing::warn!("Discarding a subscription that was submitted too fast");
+        return Ok(pending_confirmation());
+    }
+    if !bot_protection
+        .verify_captcha(data.form.captcha_response.as_deref())
+        .await
+        .context("Failed to verify the captcha response.")?
+    {
+        return Err(SubscribeError::ValidationError(
+            "The captcha verification failed.".into(),
+        ));
+    }
+
+    let source = data.source;
+    let new_subscriber = data
+        .parse(**email_normalization)
+        .map_err(SubscribeError::ValidationError)?;
+
+    email_verifier
+        .verify(&new_subscriber.email)
+        .await
+        .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
 
     // Did the subscriber attempt to register before?
     let user_id = check_existing_subscriber(&new_subscriber, &pool)
         .await
         .context("Failed to query to the database.")?;
 
-    let subscription_token = generate_subscription_token();
+    // Signed tokens are not stored: they are issued once the ID of the subscriber
+    // is known.
+    let stored_token = match token_signer {
+        Some(_) => None,
+        None => Some(token_generator.generate()),
+    };
 
-    match user_id {
+    let subscriber_id = match user_id {
         None => {
             let mut transaction = pool
                 .begin()
@@ -81,32 +221,78 @@ pub async fn subscribe(
                 .await
                 .context("Failed to insert new subscriber in the database.")?;
 
-            store_token(&mut transaction, subscriber_id, &subscription_token)
-                .await
-                .context("Failed to store the confirmation token for a new subscriber.")?;
+            if let Some(token) = &stored_token {
+                store_token(&mut transaction, subscriber_id, token)
+                    .await
+                    .context("Failed to store the confirmation token for a new subscriber.")?;
+            }
+
+            record_consent_event(
+                &mut transaction,
+                subscriber_id,
+                ConsentEventKind::Subscribe,
+                source,
+                &consent,
+            )
+            .await
+            .context("Failed to record the consent of a new subscriber.")?;
 
             transaction
                 .commit()
                 .await
                 .context("Failed to commit SQL transaction to store a new subscriber.")?;
+
+            subscriber_id
         }
         Some(id) => {
-            update_token(&pool, &id, &subscription_token)
+            if let Some(token) = &stored_token {
+                update_token(&pool, &id, token)
+                    .await
+                    .context("Failed to update the confirmation token for a new subscriber.")?;
+            }
+            update_locale(&pool, &id, new_subscriber.locale)
                 .await
-                .context("Failed to update the confirmation token for a new subscriber.")?;
+                .context("Failed to update the locale of a new subscriber.")?;
+
+            record_consent_event(
+                pool.get_ref(),
+                id,
+                ConsentEventKind::Subscribe,
+                source,
+                &consent,
+            )
+            .await
+            .context("Failed to record the consent of a new subscriber.")?;
+
+            id
         }
-    }
+    };
+
+    let confirmation_token = match (&token_signer, stored_token) {
+        (Some(signer), _) => signer.sign(subscriber_id, TokenPurpose::Confirm),
+        (None, Some(token)) => token.as_ref().to_string(),
+        (None, None) => unreachable!("Tokens are generated when they are not signed."),
+    };
 
     send_confirmation_email(
         &email_client,
+        &pool,
         new_subscriber,
         &base_url.0,
-        &subscription_token,
+        &confirmation_token,
     )
     .await
     .context("Failed to send a confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    record_subscription();
+
+    Ok(pending_confirmation())
+}
+
+fn pending_confirmation() -> HttpResponse {
+    HttpResponse::Ok().json(SubscribeResponse {
+        status: "pending_confirmation",
+    })
 }
 
 #[tracing::instrument(
@@ -121,13 +307,17 @@ pub async fn insert_subscriber(
 
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id, email, normalized_email, name, subscribed_at, status, locale
+        )
+        VALUES ($1, $2, $3, $4, $5, 'pending_confirmation', $6)
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.normalized(),
         new_subscriber.name.as_ref(),
         Utc::now(),
+        new_subscriber.locale.as_str(),
     )
     .execute(transaction)
     .await?;
@@ -135,41 +325,53 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
+/// Send the confirmation email to a new subscriber.
+///
+/// # Description
+///
+/// The email is written in the locale of the subscriber. Addresses covered by the
+/// suppression list are skipped silently, so the caller can't tell whether the
+/// email was sent or not.
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(email_client, pool, new_subscriber, base_url, confirmation_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    pool: &PgPool,
     new_subscriber: NewSubscriber,
     base_url: &str,
-    subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    confirmation_token: &str,
+) -> Result<(), anyhow::Error> {
+    if is_suppressed(pool, &new_subscriber.email)
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!(
+            subscriber_email = %pii(&new_subscriber.email),
+            "Skipping a confirmation email to a suppressed address",
+        );
+        return Ok(());
+    }
+
     // A dummy link by now.
     let confirmation_link =
-        &format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br /> \
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+        &format!("{base_url}/subscriptions/confirm?subscription_token={confirmation_token}");
+    let catalog = new_subscriber.locale.catalog();
+    let plain_body = catalog.confirmation_text(confirmation_link);
+    let html_body = catalog.confirmation_html(confirmation_link);
 
     // Send a (useless) email to the new subscriber.
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
-}
+        .send_email(
+            &new_subscriber.email,
+            catalog.confirmation_subject,
+            &html_body,
+            &plain_body,
+        )
+        .await?;
 
-fn generate_subscription_token() -> String {
-    let mut rng = thread_rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -179,12 +381,12 @@ fn generate_subscription_token() -> String {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        r#"INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id)
         VALUES ($1, $2)"#,
-        subscription_token,
+        subscription_token.hash(),
         subscriber_id,
     )
     .execute(transaction)
@@ -200,18 +402,53 @@ pub async fn store_token(
 ///
 /// This function is useful when a new subscriber attempts to subscriber multiple
 /// times before an existing token gets confirmed. The existing token gets deleted
-/// and a new token is inserted waiting for the confirmation.
-#[tracing::instrument(name = "Update a subscription token in the database", skip(pool))]
+/// and a new token is inserted waiting for the confirmation. Subscribers created
+/// while the tokens were signed have no stored token, so one is inserted for them.
+#[tracing::instrument(
+    name = "Update a subscription token in the database",
+    skip(pool, subscription_token)
+)]
 async fn update_token(
     pool: &PgPool,
     subscriber_id: &Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    let updated = sqlx::query!(
         r#"UPDATE subscription_tokens
-        SET subscription_token = $1
+        SET subscription_token_hash = $1
         WHERE subscriber_id = $2"#,
-        subscription_token,
+        subscription_token.hash(),
+        subscriber_id,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        sqlx::query!(
+            r#"INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id)
+            VALUES ($1, $2)"#,
+            subscription_token.hash(),
+            subscriber_id,
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Update the locale of a subscriber that subscribes again, so the emails follow the
+/// language of their latest request.
+#[tracing::instrument(name = "Update the locale of a subscriber", skip(pool))]
+async fn update_locale(
+    pool: &PgPool,
+    subscriber_id: &Uuid,
+    locale: Locale,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET locale = $1 WHERE id = $2",
+        locale.as_str(),
         subscriber_id,
     )
     .execute(pool)
@@ -226,7 +463,8 @@ async fn update_token(
 ///
 /// This internal function performs a SQL query to check whether the email of a new
 /// subscriber was registered previously in the DB or not. If the email was registered,
-/// the ID of the client is returned, `None` is returned otherwise.
+/// the ID of the client is returned, `None` is returned otherwise. Emails are compared
+/// using their normalized form, see [EmailNormalization].
 #[tracing::instrument(
     name = "Check if a subscriber was registered previously",
     skip(new_subscriber, pool)
@@ -237,8 +475,8 @@ async fn check_existing_subscriber(
 ) -> Result<Option<Uuid>, sqlx::Error> {
     // Check if the email is present in the `subscriptions` table.
     let existing_id = sqlx::query!(
-        "SELECT id FROM subscriptions WHERE email = $1",
-        new_subscriber.email.as_ref(),
+        "SELECT id FROM subscriptions WHERE normalized_email = $1",
+        new_subscriber.email.normalized(),
     )
     .fetch_optional(pool)
     .await?;
@@ -317,6 +555,8 @@ pub fn error_chain_fmt(
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("The content type {0:?} is not supported.")]
+    UnsupportedContentType(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -331,7 +571,23 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            SubscribeError::ValidationError(e) => {
+                ErrorBody::new("Invalid subscriber data.", vec![e.clone()])
+            }
+            SubscribeError::UnsupportedContentType(_) => ErrorBody::new(
+                self.to_string(),
+                vec!["Use either application/x-www-form-urlencoded or application/json.".into()],
+            ),
+            SubscribeError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
 }
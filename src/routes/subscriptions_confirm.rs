@@ -1,5 +1,11 @@
-use actix_web::{get, web, HttpResponse, Responder};
-use sqlx::PgPool;
+use crate::consent::{record_consent_event, ConsentContext, ConsentEventKind};
+use crate::domain::SubscriptionToken;
+use crate::metrics::record_confirmation;
+use crate::routes::{error_chain_fmt, json_error_response, ErrorBody};
+use crate::signed_token::{is_signed_token, SignedTokenError, TokenPurpose, TokenSigner};
+use actix_web::{get, http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -7,52 +13,119 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+/// Get endpoint to confirm a pending subscriber.
+///
+/// # Description
+///
+/// The token of the confirmation link is either a token stored in the DB or, when
+/// signed tokens are enabled, a signed token that is verified without querying the
+/// DB. Tokens stored in the DB are accepted in both cases, so the links that were
+/// emailed before enabling signed tokens keep working.
+///
+/// Signed tokens stay valid after the subscriber they were issued for is erased, so
+/// tokens that don't match any subscription are rejected as unknown.
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, consent, token_signer)
+)]
 #[get("/subscriptions/confirm")]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
-) -> impl Responder {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    consent: ConsentContext,
+    token_signer: Option<web::Data<TokenSigner>>,
+) -> Result<HttpResponse, ConfirmError> {
+    let token = parameters.0.subscription_token;
 
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
+    let subscriber_id = match (is_signed_token(&token), token_signer) {
+        (true, Some(signer)) => signer.verify(&token, TokenPurpose::Confirm)?,
+        (true, None) => return Err(ConfirmError::UnknownToken),
+        (false, _) => {
+            // Malformed tokens can't belong to any subscriber.
+            let subscription_token =
+                SubscriptionToken::parse(token).map_err(|_| ConfirmError::UnknownToken)?;
+            get_subscriber_id_from_token(&pool, &subscription_token)
+                .await
+                .context("Failed to retrieve the subscriber associated with the provided token.")?
+                .ok_or(ConfirmError::UnknownToken)?
         }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let previous_status = confirm_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to `confirmed`.")?
+        .ok_or(ConfirmError::UnknownToken)?;
+    // Following the link again doesn't change the consent of the subscriber.
+    if previous_status == "confirmed" {
+        return Ok(HttpResponse::Ok().finish());
     }
+
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentEventKind::Confirm,
+        "confirmation_link",
+        &consent,
+    )
+    .await
+    .context("Failed to record the confirmation of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+
+    record_confirmation();
+
+    Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscriber_id))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+/// Mark a subscriber as confirmed, returning their previous status: `None` when the
+/// subscriber doesn't exist.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(transaction, subscriber_id)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        WITH previous AS (SELECT id, status FROM subscriptions WHERE id = $1 FOR UPDATE)
+        UPDATE subscriptions SET status = 'confirmed'
+        FROM previous
+        WHERE subscriptions.id = previous.id
+        RETURNING previous.status AS "previous_status!"
+        "#,
         subscriber_id,
     )
-    .execute(pool)
+    .fetch_optional(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
 
-    Ok(())
+    Ok(result.map(|r| r.previous_status))
 }
 
+/// Get the subscriber that a token belongs to.
+///
+/// # Description
+///
+/// Tokens are looked up by their hash, as only the hash is stored.
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
 ) -> Result<Option<Uuid>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token,
+        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token_hash = $1"#,
+        subscription_token.hash(),
     )
     .fetch_optional(pool)
     .await
@@ -63,3 +136,41 @@ pub async fn get_subscriber_id_from_token(
 
     Ok(result.map(|r| r.subscriber_id))
 }
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    InvalidSignedToken(#[from] SignedTokenError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::UnknownToken | ConfirmError::InvalidSignedToken(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            ConfirmError::UnknownToken | ConfirmError::InvalidSignedToken(_) => {
+                ErrorBody::new(self.to_string(), Vec::new())
+            }
+            ConfirmError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
+}
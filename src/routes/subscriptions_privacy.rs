@@ -0,0 +1,474 @@
+//! Module that includes the endpoints to honour the privacy requests of subscribers.
+//!
+//! # Description
+//!
+//! Subscribers can ask for a copy of everything that is stored about them, or ask
+//! to be forgotten. Both flows start with a request that includes the email of the
+//! subscriber: a privacy token is emailed back to them, so only the owner of the
+//! address can go ahead with the request. Then:
+//! - The export link emails the subscriber a JSON export of their data.
+//! - The erasure link shows a page that asks the subscriber to confirm the erasure,
+//!   so that link scanners of mail providers can't erase anyone by following it.
+//!   Once confirmed, the subscription is deleted along with its tokens, and the
+//!   delivery records of the issues sent to them are anonymized, so the delivery
+//!   reports keep their counts.
+
+use crate::consent::{get_consent_events, ConsentEvent};
+use crate::domain::{EmailNormalization, SubscriberEmail, SubscriptionToken, TokenGenerator};
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::routes::{error_chain_fmt, is_suppressed, json_error_response, ErrorBody};
+use crate::startup::ApplicationBaseUrl;
+use crate::telemetry::pii;
+use actix_web::http::header::ContentType;
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Time during which a privacy token can be used.
+const PRIVACY_TOKEN_VALIDITY_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct PrivacyRequestData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    privacy_token: String,
+}
+
+/// Body of the responses of the privacy endpoints.
+#[derive(serde::Serialize)]
+struct PrivacyResponse {
+    status: &'static str,
+}
+
+/// Everything that is stored about a subscriber.
+#[derive(serde::Serialize)]
+struct SubscriberExport {
+    subscription: SubscriptionRecord,
+    subscription_token_hashes: Vec<String>,
+    deliveries: Vec<DeliveryRecord>,
+    consent_events: Vec<ConsentEvent>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    locale: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryRecord {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    provider_message_id: Option<String>,
+    failure_reason: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+/// Post endpoint to start a privacy request.
+///
+/// # Description
+///
+/// An email with the links to export or erase the data is sent to the subscriber.
+/// The response is the same whether the email belongs to a subscriber or not, so
+/// the endpoint can't be used to find out who is subscribed. The email is sent to
+/// the address that is stored for the subscriber, unless it is suppressed.
+#[tracing::instrument(
+    name = "Request access to the data of a subscriber",
+    skip(
+        body,
+        pool,
+        email_client,
+        base_url,
+        token_generator,
+        email_normalization
+    )
+)]
+#[post("/subscriptions/privacy")]
+pub async fn request_privacy_token(
+    body: web::Json<PrivacyRequestData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_generator: web::Data<TokenGenerator>,
+    email_normalization: web::Data<EmailNormalization>,
+) -> Result<HttpResponse, PrivacyError> {
+    let email = SubscriberEmail::parse_with(body.0.email, **email_normalization)
+        .map_err(PrivacyError::ValidationError)?;
+
+    let subscriber = sqlx::query!(
+        "SELECT id, email, locale FROM subscriptions WHERE normalized_email = $1",
+        email.normalized(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to query to the database.")?;
+
+    if let Some(subscriber) = subscriber {
+        let email = SubscriberEmail::parse_with(subscriber.email, **email_normalization)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("The stored email of the subscriber is invalid.")?;
+        if is_suppressed(&pool, &email)
+            .await
+            .context("Failed to check the suppression list.")?
+        {
+            tracing::info!(
+                subscriber_email = %pii(&email),
+                "Skipping a privacy email to a suppressed address",
+            );
+            return Ok(HttpResponse::Ok().json(PrivacyResponse {
+                status: "privacy_email_sent",
+            }));
+        }
+
+        let privacy_token = token_generator.generate();
+        store_privacy_token(&pool, subscriber.id, &privacy_token)
+            .await
+            .context("Failed to store a privacy token.")?;
+
+        let catalog = Locale::parse(&subscriber.locale)
+            .unwrap_or_default()
+            .catalog();
+        let export_link = format!(
+            "{}/subscriptions/privacy/export?privacy_token={}",
+            base_url.0,
+            privacy_token.as_ref()
+        );
+        let erasure_link = format!(
+            "{}/subscriptions/privacy/erase?privacy_token={}",
+            base_url.0,
+            privacy_token.as_ref()
+        );
+
+        email_client
+            .send_email(
+                &email,
+                catalog.privacy_subject,
+                &catalog.privacy_html(&export_link, &erasure_link),
+                &catalog.privacy_text(&export_link, &erasure_link),
+            )
+            .await
+            .context("Failed to send the privacy email.")?;
+    }
+
+    Ok(HttpResponse::Ok().json(PrivacyResponse {
+        status: "privacy_email_sent",
+    }))
+}
+
+/// Get endpoint that emails a subscriber a JSON export of their data.
+///
+/// # Description
+///
+/// Nothing is sent when the email of the subscriber has been suppressed since the
+/// privacy token was issued.
+#[tracing::instrument(
+    name = "Export the data of a subscriber",
+    skip(parameters, pool, email_client, email_normalization)
+)]
+#[get("/subscriptions/privacy/export")]
+pub async fn export_subscriber_data(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    email_normalization: web::Data<EmailNormalization>,
+) -> Result<HttpResponse, PrivacyError> {
+    let privacy_token = SubscriptionToken::parse(parameters.0.privacy_token)
+        .map_err(|_| PrivacyError::UnknownToken)?;
+    let subscriber_id = get_subscriber_id_from_privacy_token(&pool, &privacy_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PrivacyError::UnknownToken)?;
+
+    let export = get_subscriber_export(&pool, subscriber_id)
+        .await
+        .context("Failed to gather the data of the subscriber.")?;
+    let email =
+        SubscriberEmail::parse_with(export.subscription.email.clone(), **email_normalization)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("The stored email of the subscriber is invalid.")?;
+    let catalog = Locale::parse(&export.subscription.locale)
+        .unwrap_or_default()
+        .catalog();
+    let export =
+        serde_json::to_string_pretty(&export).context("Failed to serialize the export.")?;
+
+    if is_suppressed(&pool, &email)
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!(
+            subscriber_email = %pii(&email),
+            "Skipping a data export to a suppressed address",
+        );
+        return Ok(HttpResponse::Ok().json(PrivacyResponse {
+            status: "export_sent",
+        }));
+    }
+
+    email_client
+        .send_email(
+            &email,
+            catalog.export_subject,
+            &catalog.export_html(&export),
+            &catalog.export_text(&export),
+        )
+        .await
+        .context("Failed to send the data export.")?;
+
+    Ok(HttpResponse::Ok().json(PrivacyResponse {
+        status: "export_sent",
+    }))
+}
+
+/// Get endpoint that asks the subscriber to confirm the erasure of their data.
+///
+/// # Description
+///
+/// The page is written in the language of the subscriber, and includes a form that
+/// posts the privacy token back to [erase_subscriber_data].
+#[tracing::instrument(
+    name = "Ask to confirm the erasure of a subscriber",
+    skip(parameters, pool, base_url)
+)]
+#[get("/subscriptions/privacy/erase")]
+pub async fn erasure_confirmation(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, PrivacyError> {
+    let privacy_token = SubscriptionToken::parse(parameters.0.privacy_token)
+        .map_err(|_| PrivacyError::UnknownToken)?;
+    let subscriber_id = get_subscriber_id_from_privacy_token(&pool, &privacy_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PrivacyError::UnknownToken)?;
+    let locale = sqlx::query!(
+        "SELECT locale FROM subscriptions WHERE id = $1",
+        subscriber_id,
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to get the locale of the subscriber.")?
+    .locale;
+
+    let locale = Locale::parse(&locale).unwrap_or_default();
+    let catalog = locale.catalog();
+    let page = include_str!("erasure_confirmation.html")
+        .replace("{{locale}}", locale.as_str())
+        .replace("{{title}}", catalog.erasure_title)
+        .replace("{{prompt}}", catalog.erasure_prompt)
+        .replace("{{button}}", catalog.erasure_button)
+        .replace("{{base_url}}", &base_url.0)
+        .replace("{{privacy_token}}", privacy_token.as_ref());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page))
+}
+
+/// Post endpoint that erases the data of a subscriber.
+#[tracing::instrument(name = "Erase the data of a subscriber", skip(form, pool))]
+#[post("/subscriptions/privacy/erase")]
+pub async fn erase_subscriber_data(
+    form: web::Form<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PrivacyError> {
+    let privacy_token =
+        SubscriptionToken::parse(form.0.privacy_token).map_err(|_| PrivacyError::UnknownToken)?;
+    let subscriber_id = get_subscriber_id_from_privacy_token(&pool, &privacy_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PrivacyError::UnknownToken)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    erase_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to erase the data of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+
+    Ok(HttpResponse::Ok().json(PrivacyResponse { status: "erased" }))
+}
+
+#[tracing::instrument(name = "Store a privacy token", skip(pool, privacy_token))]
+async fn store_privacy_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    privacy_token: &SubscriptionToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO privacy_tokens (privacy_token_hash, subscriber_id, created_at)
+        VALUES ($1, $2, $3)"#,
+        privacy_token.hash(),
+        subscriber_id,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the subscriber of a privacy token, as long as the token has not expired.
+#[tracing::instrument(name = "Get subscriber_id from privacy token", skip_all)]
+async fn get_subscriber_id_from_privacy_token(
+    pool: &PgPool,
+    privacy_token: &SubscriptionToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT subscriber_id FROM privacy_tokens
+        WHERE privacy_token_hash = $1 AND created_at > $2"#,
+        privacy_token.hash(),
+        Utc::now() - Duration::hours(PRIVACY_TOKEN_VALIDITY_HOURS),
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.map(|r| r.subscriber_id))
+}
+
+#[tracing::instrument(name = "Gather the data of a subscriber", skip(pool))]
+async fn get_subscriber_export(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<SubscriberExport, sqlx::Error> {
+    let subscription = sqlx::query_as!(
+        SubscriptionRecord,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let subscription_token_hashes = sqlx::query!(
+        "SELECT subscription_token_hash FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| r.subscription_token_hash)
+    .collect();
+
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            i.title,
+            d.status,
+            d.provider_message_id,
+            d.failure_reason,
+            d.updated_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_email = $1
+        ORDER BY d.updated_at
+        "#,
+        subscription.email,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let consent_events = get_consent_events(pool, subscriber_id).await?;
+
+    Ok(SubscriberExport {
+        subscription,
+        subscription_token_hashes,
+        deliveries,
+        consent_events,
+    })
+}
+
+/// Erase a subscriber and anonymize their delivery records.
+///
+/// # Description
+///
+/// The tokens and the consent events of the subscriber are deleted along with the
+/// subscription, as their foreign keys cascade. Delivery records are kept, so the
+/// delivery reports of past issues don't change, but they no longer include the
+/// email of the subscriber nor anything the email provider returned about it.
+#[tracing::instrument(name = "Erase a subscriber", skip(transaction))]
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let subscriber = sqlx::query!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING email",
+        subscriber_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET subscriber_email = $2, provider_message_id = NULL, failure_reason = NULL
+        WHERE subscriber_email = $1
+        "#,
+        subscriber.email,
+        format!("erased:{}", Uuid::new_v4()),
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum PrivacyError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The privacy token is unknown or has expired.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PrivacyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PrivacyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PrivacyError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PrivacyError::UnknownToken => StatusCode::UNAUTHORIZED,
+            PrivacyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            PrivacyError::ValidationError(e) => {
+                ErrorBody::new("Invalid privacy request.", vec![e.clone()])
+            }
+            PrivacyError::UnknownToken => ErrorBody::new(self.to_string(), Vec::new()),
+            PrivacyError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
+}
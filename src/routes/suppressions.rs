@@ -0,0 +1,247 @@
+//! Module that includes the endpoints to manage the suppression list.
+//!
+//! # Description
+//!
+//! The suppression list holds email addresses and whole domains that must never
+//! receive an email from the newsletter application, whatever their subscription
+//! status is. It is consulted before sending confirmation emails and newsletter
+//! issues.
+
+use crate::authentication::AdminUser;
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use crate::routes::{error_chain_fmt, json_error_response, ErrorBody};
+use crate::telemetry::pii;
+use actix_web::http::StatusCode;
+use actix_web::{delete, post, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Kind of entry of the suppression list.
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SuppressionKind {
+    /// A single email address.
+    Address,
+    /// Every address that belongs to a domain.
+    Domain,
+}
+
+impl SuppressionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionKind::Address => "address",
+            SuppressionKind::Domain => "domain",
+        }
+    }
+}
+
+/// Data that is included in the JSON body of a request to add a suppression.
+#[derive(serde::Deserialize)]
+pub struct SuppressionData {
+    kind: SuppressionKind,
+    value: String,
+    reason: Option<String>,
+}
+
+/// Validated value of a suppression entry.
+struct Suppression {
+    kind: SuppressionKind,
+    value: String,
+    reason: Option<String>,
+}
+
+impl SuppressionData {
+    /// Validate the entry, normalizing addresses as configured.
+    fn parse(self, normalization: EmailNormalization) -> Result<Suppression, String> {
+        let value = match self.kind {
+            SuppressionKind::Address => SubscriberEmail::parse_with(self.value, normalization)?
+                .normalized()
+                .to_string(),
+            SuppressionKind::Domain => parse_domain(self.value)?,
+        };
+
+        Ok(Suppression {
+            kind: self.kind,
+            value,
+            reason: self.reason,
+        })
+    }
+}
+
+fn parse_domain(s: String) -> Result<String, String> {
+    let domain = s.trim().trim_start_matches('@');
+
+    if domain.is_empty() || domain.contains('@') || domain.contains(char::is_whitespace) {
+        return Err(format!("{s} is not a valid domain."));
+    }
+
+    idna::domain_to_ascii(domain).map_err(|_| format!("{s} is not a valid domain."))
+}
+
+/// Post endpoint to add an address or a domain to the suppression list.
+///
+/// # Description
+///
+/// Adding an entry that already exists only refreshes its reason.
+#[tracing::instrument(
+    name = "Adding a suppression",
+    skip(_admin, body, pool),
+    fields(
+        suppression_kind = ?body.kind,
+        suppression_value = %pii(&body.value),
+    )
+)]
+#[post("/admin/suppressions")]
+pub async fn add_suppression(
+    _admin: AdminUser,
+    body: web::Json<SuppressionData>,
+    pool: web::Data<PgPool>,
+    email_normalization: web::Data<EmailNormalization>,
+) -> Result<HttpResponse, SuppressionError> {
+    let suppression = body
+        .into_inner()
+        .parse(**email_normalization)
+        .map_err(SuppressionError::ValidationError)?;
+
+    insert_suppression(&pool, &suppression)
+        .await
+        .context("Failed to insert the suppression in the database.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Body of the requests to remove a suppression.
+#[derive(serde::Deserialize)]
+pub struct SuppressionRemoval {
+    value: String,
+}
+
+/// Delete endpoint to remove an address or a domain from the suppression list.
+///
+/// # Description
+///
+/// The value is sent in the body rather than in the path, so the addresses aren't
+/// recorded in the target of the request spans.
+#[tracing::instrument(
+    name = "Removing a suppression",
+    skip_all,
+    fields(suppression_value = %pii(&body.value))
+)]
+#[delete("/admin/suppressions")]
+pub async fn remove_suppression(
+    _admin: AdminUser,
+    body: web::Json<SuppressionRemoval>,
+    pool: web::Data<PgPool>,
+    email_normalization: web::Data<EmailNormalization>,
+) -> Result<HttpResponse, SuppressionError> {
+    let value = body.into_inner().value;
+    // Entries are stored normalized, look them up in the same way.
+    let value = match SubscriberEmail::parse_with(value.clone(), **email_normalization) {
+        Ok(email) => email.normalized().to_string(),
+        Err(_) => parse_domain(value.clone()).unwrap_or(value),
+    };
+    let deleted = delete_suppression(&pool, &value)
+        .await
+        .context("Failed to delete the suppression from the database.")?;
+
+    if deleted {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Err(SuppressionError::UnknownSuppression)
+    }
+}
+
+#[tracing::instrument(name = "Saving a suppression in the database", skip(pool, suppression))]
+async fn insert_suppression(pool: &PgPool, suppression: &Suppression) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (id, kind, value, reason, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (value) DO UPDATE SET reason = EXCLUDED.reason
+        "#,
+        Uuid::new_v4(),
+        suppression.kind.as_str(),
+        suppression.value,
+        suppression.reason,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Deleting a suppression from the database", skip_all)]
+async fn delete_suppression(pool: &PgPool, value: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM suppressions WHERE value = $1", value)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Check whether an email address is covered by the suppression list.
+///
+/// # Description
+///
+/// An address is suppressed when either the address itself or its domain are
+/// registered in the `suppressions` table. Both checks use the normalized form of
+/// the email, so the case of the domain doesn't matter.
+#[tracing::instrument(name = "Check if an email is suppressed", skip(pool, email))]
+pub async fn is_suppressed(pool: &PgPool, email: &SubscriberEmail) -> Result<bool, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM suppressions
+            WHERE (kind = 'address' AND value = $1)
+               OR (kind = 'domain' AND value = $2)
+        ) AS "suppressed!"
+        "#,
+        email.normalized(),
+        email.normalized().rsplit_once('@').map(|(_, d)| d),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.suppressed)
+}
+
+#[derive(thiserror::Error)]
+pub enum SuppressionError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The value is not in the suppression list.")]
+    UnknownSuppression,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SuppressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SuppressionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SuppressionError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SuppressionError::UnknownSuppression => StatusCode::NOT_FOUND,
+            SuppressionError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            SuppressionError::ValidationError(e) => {
+                ErrorBody::new("Invalid suppression.", vec![e.clone()])
+            }
+            SuppressionError::UnknownSuppression => ErrorBody::new(self.to_string(), Vec::new()),
+            SuppressionError::UnexpectedError(_) => ErrorBody::internal(),
+        };
+
+        json_error_response(self.status_code(), body)
+    }
+}
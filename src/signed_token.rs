@@ -0,0 +1,295 @@
+//! Module that includes stateless tokens signed with HMAC-SHA256.
+//!
+//! # Description
+//!
+//! Signed tokens are an alternative to the tokens stored in the DB for the links
+//! that are emailed to subscribers. A signed token includes the ID of the
+//! subscriber, the purpose of the link and its expiry time, along with a signature
+//! of all of them. This way, tokens are verified without a DB round trip, and a
+//! token issued for a purpose can't be used for another one.
+//!
+//! Tokens look like `{key_id}.{purpose}.{subscriber_id}.{expires_at}.{signature}`.
+//! The ID of the key that signed the token lets the [TokenSigner] pick the right
+//! key to verify it, so keys can be rotated: new tokens are signed with the first
+//! key, and the rest of the keys are only used to verify the tokens that were
+//! signed before the rotation.
+
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a signed token can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    Confirm,
+    Unsubscribe,
+    Preferences,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::Confirm => "confirm",
+            TokenPurpose::Unsubscribe => "unsubscribe",
+            TokenPurpose::Preferences => "preferences",
+        }
+    }
+}
+
+/// Key used to sign or verify tokens.
+#[derive(Debug)]
+pub struct SigningKey {
+    id: String,
+    secret: Secret<String>,
+}
+
+impl SigningKey {
+    /// Build a signing key.
+    ///
+    /// # Description
+    ///
+    /// The ID of the key is included in the tokens, so it must be made of ASCII
+    /// letters, digits, `-` or `_`.
+    pub fn new(id: String, secret: Secret<String>) -> Result<SigningKey, String> {
+        let is_valid_id = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid_id {
+            return Err(format!("{id:?} is not a valid ID for a signing key."));
+        }
+        if secret.expose_secret().len() < 32 {
+            return Err(format!(
+                "The secret of the signing key {id} must be at least 32 characters long."
+            ));
+        }
+
+        Ok(Self { id, secret })
+    }
+
+    /// Sign a payload, returning the hex-encoded signature.
+    pub fn sign(&self, payload: &str) -> String {
+        hex::encode(self.mac(payload).finalize().into_bytes())
+    }
+
+    /// Check, in constant time, the hex-encoded signature of a payload.
+    pub fn verify(&self, payload: &str, signature: &str) -> bool {
+        hex::decode(signature).is_ok_and(|signature| self.mac(payload).verify(&signature).is_ok())
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Errors found while verifying a signed token.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SignedTokenError {
+    #[error("The token is malformed.")]
+    Malformed,
+    #[error("The token was signed with an unknown key.")]
+    UnknownKey,
+    #[error("The signature of the token is invalid.")]
+    InvalidSignature,
+    #[error("The token can't be used for this purpose.")]
+    WrongPurpose,
+    #[error("The token has expired.")]
+    Expired,
+}
+
+/// Issuer and verifier of signed tokens.
+pub struct TokenSigner {
+    keys: Vec<SigningKey>,
+    validity: Duration,
+}
+
+impl TokenSigner {
+    /// Build a signer. The first key is used to sign new tokens, and all of them are
+    /// used to verify tokens.
+    pub fn new(keys: Vec<SigningKey>, validity: Duration) -> Result<TokenSigner, String> {
+        if keys.is_empty() {
+            return Err("At least one signing key is required.".into());
+        }
+
+        Ok(Self { keys, validity })
+    }
+
+    /// Issue a token for a subscriber.
+    pub fn sign(&self, subscriber_id: Uuid, purpose: TokenPurpose) -> String {
+        let key = &self.keys[0];
+        let expires_at = (Utc::now() + self.validity).timestamp();
+        let payload = format!(
+            "{}.{}.{}.{}",
+            key.id,
+            purpose.as_str(),
+            subscriber_id.to_simple(),
+            expires_at
+        );
+        let signature = key.sign(&payload);
+
+        format!("{payload}.{signature}")
+    }
+
+    /// Verify a token, returning the ID of the subscriber it was issued for.
+    ///
+    /// # Description
+    ///
+    /// The signature is checked before anything else in the token is trusted, and
+    /// it is compared in constant time.
+    pub fn verify(&self, token: &str, purpose: TokenPurpose) -> Result<Uuid, SignedTokenError> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(SignedTokenError::Malformed)?;
+        let parts: Vec<&str> = payload.split('.').collect();
+        let [key_id, token_purpose, subscriber_id, expires_at] = parts[..] else {
+            return Err(SignedTokenError::Malformed);
+        };
+
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.id == key_id)
+            .ok_or(SignedTokenError::UnknownKey)?;
+        let signature = hex::decode(signature).map_err(|_| SignedTokenError::Malformed)?;
+        key.mac(payload)
+            .verify(&signature)
+            .map_err(|_| SignedTokenError::InvalidSignature)?;
+
+        if token_purpose != purpose.as_str() {
+            return Err(SignedTokenError::WrongPurpose);
+        }
+        let expires_at: i64 = expires_at
+            .parse()
+            .map_err(|_| SignedTokenError::Malformed)?;
+        if expires_at < Utc::now().timestamp() {
+            return Err(SignedTokenError::Expired);
+        }
+
+        Uuid::parse_str(subscriber_id).map_err(|_| SignedTokenError::Malformed)
+    }
+}
+
+/// Check whether a token looks like a signed token, as opposed to the tokens that
+/// are stored in the DB, which are purely alphanumeric.
+pub fn is_signed_token(token: &str) -> bool {
+    token.contains('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SignedTokenError, SigningKey, TokenPurpose, TokenSigner};
+    use chrono::Duration;
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn key(id: &str) -> SigningKey {
+        SigningKey::new(
+            id.into(),
+            Secret::new(format!("{id}-a-very-long-secret-to-sign-the-tokens")),
+        )
+        .unwrap()
+    }
+
+    fn signer(keys: &[&str]) -> TokenSigner {
+        TokenSigner::new(keys.iter().map(|id| key(id)).collect(), Duration::hours(1)).unwrap()
+    }
+
+    #[test]
+    fn signed_tokens_are_verified() {
+        let signer = signer(&["k1"]);
+        let subscriber_id = Uuid::new_v4();
+        let token = signer.sign(subscriber_id, TokenPurpose::Confirm);
+
+        assert_ok_eq!(signer.verify(&token, TokenPurpose::Confirm), subscriber_id);
+    }
+
+    #[test]
+    fn tokens_are_bound_to_their_purpose() {
+        let signer = signer(&["k1"]);
+        let token = signer.sign(Uuid::new_v4(), TokenPurpose::Unsubscribe);
+
+        assert_eq!(
+            signer.verify(&token, TokenPurpose::Confirm),
+            Err(SignedTokenError::WrongPurpose)
+        );
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let signer = signer(&["k1"]);
+        let token = signer.sign(Uuid::new_v4(), TokenPurpose::Confirm);
+        let tampered = token.replace(".confirm.", ".preferences.");
+
+        assert_eq!(
+            signer.verify(&tampered, TokenPurpose::Preferences),
+            Err(SignedTokenError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let signer = TokenSigner::new(vec![key("k1")], Duration::seconds(-1)).unwrap();
+        let token = signer.sign(Uuid::new_v4(), TokenPurpose::Confirm);
+
+        assert_eq!(
+            signer.verify(&token, TokenPurpose::Confirm),
+            Err(SignedTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn tokens_signed_with_a_rotated_key_are_still_verified() {
+        let old_signer = signer(&["k1"]);
+        let new_signer = signer(&["k2", "k1"]);
+        let subscriber_id = Uuid::new_v4();
+        let token = old_signer.sign(subscriber_id, TokenPurpose::Confirm);
+
+        assert_ok_eq!(
+            new_signer.verify(&token, TokenPurpose::Confirm),
+            subscriber_id
+        );
+        assert!(new_signer
+            .sign(subscriber_id, TokenPurpose::Confirm)
+            .starts_with("k2."));
+    }
+
+    #[test]
+    fn tokens_signed_with_a_retired_key_are_rejected() {
+        let token = signer(&["k1"]).sign(Uuid::new_v4(), TokenPurpose::Confirm);
+
+        assert_eq!(
+            signer(&["k2"]).verify(&token, TokenPurpose::Confirm),
+            Err(SignedTokenError::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let signer = signer(&["k1"]);
+
+        assert_eq!(
+            signer.verify("abcdefghijklmnopqrstuvwxy", TokenPurpose::Confirm),
+            Err(SignedTokenError::Malformed)
+        );
+        assert_eq!(
+            signer.verify("k1.confirm.abc.def", TokenPurpose::Confirm),
+            Err(SignedTokenError::Malformed)
+        );
+    }
+
+    #[test]
+    fn short_secrets_and_invalid_key_ids_are_rejected() {
+        assert_err!(SigningKey::new("k1".into(), Secret::new("short".into())));
+        assert_err!(SigningKey::new(
+            "k.1".into(),
+            Secret::new("a-very-long-secret-key-1234567890".into())
+        ));
+    }
+}
@@ -2,18 +2,34 @@
 
 use crate::configuration::DatabaseSettings;
 use crate::configuration::Settings;
+use crate::consent::TrustedProxies;
+use crate::metrics::record_request;
 use crate::routes;
+use crate::tls::{self, watch_certificate, CertificateResolver};
 use crate::EmailClient;
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_cors::Cors;
+use actix_web::dev::{Server, ServerHandle, Service};
+use actix_web::http::header;
+use actix_web::http::uri::Authority;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use anyhow::Context;
+use rustls::ServerConfig;
+use sqlx::migrate::MigrateError;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinHandle;
 use tracing_actix_web::TracingLogger;
 
-// A type to hold the newly built server and its port
+// A type to hold the newly built server, its port and its DB pool
 pub struct Application {
     port: u16,
     server: Server,
+    db_pool: PgPool,
+    redirect: Option<(u16, Server)>,
+    certificate_watcher: Option<JoinHandle<()>>,
 }
 
 impl Application {
@@ -21,19 +37,14 @@ impl Application {
         // Create a connection pool to handle connections to the DB.
         let connection_pool = get_connection_pool(&configuration.database);
 
+        if configuration.database.migrate_on_startup {
+            run_migrations(&configuration.database)
+                .await
+                .map_err(|e| std::io::Error::other(format!("{e:#}")))?;
+        }
+
         // Build an `EmailClient` to handle all the stuff related to sending mails.
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
+        let email_client = configuration.email_client.clone().client();
 
         // Address for the service that will run the newsletter application.
         let address = format!(
@@ -44,28 +55,108 @@ impl Application {
         // Create a TcpListener to bind the address in which the service aims to listen for requests.
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+
+        // When TLS is enabled, the certificate is watched for changes, and the plain
+        // HTTP requests are optionally redirected to HTTPS.
+        let mut tls_config = None;
+        let mut redirect = None;
+        let mut certificate_watcher = None;
+        if let Some(tls) = &configuration.application.tls {
+            let resolver = CertificateResolver::new(tls.files())
+                .map_err(|e| std::io::Error::other(format!("{e:#}")))?;
+            let resolver = Arc::new(resolver);
+            tls_config = Some(tls::server_config(resolver.clone()));
+            certificate_watcher = Some(tokio::spawn(watch_certificate(
+                resolver,
+                tls.reload_interval(),
+            )));
+
+            if let Some(redirect_port) = tls.redirect_port {
+                let listener =
+                    TcpListener::bind((configuration.application.host.as_str(), redirect_port))?;
+                let redirect_port = listener.local_addr().unwrap().port();
+                redirect = Some((redirect_port, run_https_redirect(listener, port)?));
+            }
+        }
+
         let server = run(
             listener,
-            connection_pool,
+            connection_pool.clone(),
             email_client,
-            configuration.application.base_url,
+            configuration,
+            tls_config,
         )?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            db_pool: connection_pool,
+            redirect,
+            certificate_watcher,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// Port of the listener that redirects plain HTTP requests to HTTPS, if any.
+    pub fn redirect_port(&self) -> Option<u16> {
+        self.redirect.as_ref().map(|(port, _)| *port)
+    }
+
+    /// Get a handle to stop the server.
+    ///
+    /// # Description
+    ///
+    /// The server doesn't handle signals by itself, so the process can stop the API
+    /// and the background workers together. Stopping the server gracefully stops
+    /// accepting connections, and lets the in-flight requests finish within the
+    /// configured [shutdown timeout](crate::configuration::ApplicationSettings).
+    pub fn handle(&self) -> ServerHandle {
+        self.server.handle()
+    }
+
+    /// Run the server until it's stopped, and then close its DB pool.
+    ///
+    /// # Description
+    ///
+    /// The HTTPS redirect listener, if any, is stopped along with the server.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let (redirect_handle, redirect_server) = match self.redirect {
+            Some((_, server)) => (Some(server.handle()), Some(server)),
+            None => (None, None),
+        };
+        let server = async {
+            let outcome = self.server.await;
+            if let Some(redirect_handle) = redirect_handle {
+                redirect_handle.stop(true).await;
+            }
+            outcome
+        };
+        let redirect_server = async {
+            match redirect_server {
+                Some(redirect_server) => redirect_server.await,
+                None => Ok(()),
+            }
+        };
+
+        let (outcome, redirect_outcome) = tokio::join!(server, redirect_server);
+        if let Some(certificate_watcher) = self.certificate_watcher {
+            certificate_watcher.abort();
+        }
+        self.db_pool.close().await;
+
+        outcome.and(redirect_outcome)
     }
 }
 
 /// Wrapper type for a URL.
 pub struct ApplicationBaseUrl(pub String);
 
+/// Wrapper type for the maximum number of connections of the DB pool.
+pub struct MaxDbConnections(pub u32);
+
 /// Create a new HttpServer instance.
 ///
 /// # Description
@@ -73,48 +164,248 @@ pub struct ApplicationBaseUrl(pub String);
 /// This function takes the following arguments:
 /// - A [TcpListener] bind to an address and a port.
 /// - A [PgPool] that connects to a valid Postgres DB server.
+/// - An [EmailClient] to send emails.
+/// - The [Settings] of the application, used to build the rest of the services that
+///   are shared by the endpoints: the base URL of the application, the origins that
+///   are allowed to send cross-origin requests, the bot protection checks, the
+///   email verifier, the token generator and, if enabled, the token signer.
+/// - The TLS configuration, to serve HTTPS instead of plain HTTP.
 ///
 /// To constructs a new [HttpServer] and returns it.
 pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
+    configuration: Settings,
+    tls_config: Option<ServerConfig>,
 ) -> Result<Server, std::io::Error> {
+    let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    let token_generator = configuration.tokens.generator().map_err(invalid_input)?;
+    let token_signer = configuration
+        .signed_tokens
+        .signer()
+        .map_err(invalid_input)?
+        .map(web::Data::new);
+    let cors_allowed_origins = configuration.application.cors_allowed_origins.clone();
+    let shutdown_timeout = configuration.application.shutdown_timeout_seconds;
+
     // Wrap the DB's driver with a web::Data pointer. This way, the driver will
     // be safely shared between threads.
     let db_pool = web::Data::new(db_pool);
+    let max_db_connections =
+        web::Data::new(MaxDbConnections(configuration.database.max_connections));
     let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let base_url = web::Data::new(ApplicationBaseUrl(configuration.application.base_url));
+    let bot_protection = web::Data::new(
+        configuration
+            .bot_protection
+            .checks()
+            .map_err(invalid_input)?,
+    );
+    let email_normalization = web::Data::new(configuration.email_verification.normalization());
+    let email_verifier = web::Data::new(configuration.email_verification.verifier());
+    let token_generator = web::Data::new(token_generator);
+    let trusted_proxies = web::Data::new(TrustedProxies(
+        configuration.application.trusted_proxies.clone(),
+    ));
 
     // Connect all the services that are featured by the newsletter app.
     let server = HttpServer::new(move || {
-        App::new()
+        let app = App::new()
+            // Record the number and latency of the requests per route.
+            .wrap_fn(|req, srv| {
+                let started = Instant::now();
+                let method = req.method().clone();
+                let route = req.match_pattern();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await;
+                    let status = match &response {
+                        Ok(response) => response.status(),
+                        Err(e) => e.as_response_error().status_code(),
+                    };
+                    record_request(&method, route.as_deref(), status, started.elapsed());
+                    response
+                }
+            })
             // Add the Logger middleware.
             .wrap(TracingLogger::default())
-            // Get health_check endpoint.
+            // Allow the configured origins to embed the signup form.
+            .wrap(cors(&cors_allowed_origins))
+            // Liveness and readiness endpoints.
             .service(routes::health_check)
+            .service(routes::health_ready)
+            // Prometheus metrics.
+            .service(routes::metrics)
             // Post subscribe endpoint.
             .service(routes::subscribe)
+            // Embeddable signup form.
+            .service(routes::subscribe_form)
             // Confirmation endpoint.
             .service(routes::confirm)
+            // Data export and erasure endpoints.
+            .service(routes::request_privacy_token)
+            .service(routes::export_subscriber_data)
+            .service(routes::erasure_confirmation)
+            .service(routes::erase_subscriber_data)
             // Publish a newsletter endpoint.
             .service(routes::publish_newsletter)
+            // Delivery report of a newsletter issue.
+            .service(routes::issue_report)
+            // Suppression list management endpoints.
+            .service(routes::add_suppression)
+            .service(routes::remove_suppression)
+            // Consent audit log of a subscriber.
+            .service(routes::export_consent_events)
+            // Report malformed requests using the same JSON body as the endpoints.
+            .app_data(web::JsonConfig::default().error_handler(|e, _| routes::extractor_error(e)))
+            .app_data(web::FormConfig::default().error_handler(|e, _| routes::extractor_error(e)))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| routes::extractor_error(e)))
+            .app_data(web::PathConfig::default().error_handler(|e, _| routes::extractor_error(e)))
             // State of the app: the DB's driver
             .app_data(db_pool.clone())
+            .app_data(max_db_connections.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(bot_protection.clone())
+            .app_data(email_verifier.clone())
+            .app_data(email_normalization.clone())
+            .app_data(token_generator.clone())
+            .app_data(trusted_proxies.clone());
+
+        // The signer is only available to the endpoints when signed tokens are enabled.
+        match &token_signer {
+            Some(token_signer) => app.app_data(token_signer.clone()),
+            None => app,
+        }
     })
-    // Attach the listener to the app.
+    // Signals are handled by the process, see `Application::handle`.
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout);
+
+    // Attach the listener to the app, and run the server.
+    let server = match tls_config {
+        Some(tls_config) => server.listen_rustls_0_21(listener, tls_config)?,
+        None => server.listen(listener)?,
+    }
+    .run();
+
+    Ok(server)
+}
+
+/// Create an HttpServer that redirects every request to the same URL using HTTPS,
+/// on the given port.
+pub fn run_https_redirect(
+    listener: TcpListener,
+    https_port: u16,
+) -> Result<Server, std::io::Error> {
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .default_service(web::to(move |req: HttpRequest| async move {
+                match https_url(&req, https_port) {
+                    Some(url) => HttpResponse::PermanentRedirect()
+                        .insert_header((header::LOCATION, url))
+                        .finish(),
+                    None => HttpResponse::BadRequest().finish(),
+                }
+            }))
+    })
+    .disable_signals()
     .listen(listener)?
-    // And run the server.
     .run();
 
     Ok(server)
 }
 
+/// Build the HTTPS URL of a request, using the host that the client requested.
+fn https_url(req: &HttpRequest, https_port: u16) -> Option<String> {
+    let host = req
+        .headers()
+        .get(header::HOST)?
+        .to_str()
+        .ok()?
+        .parse::<Authority>()
+        .ok()?;
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    Some(match https_port {
+        443 => format!("https://{}{path}", host.host()),
+        port => format!("https://{}:{port}{path}", host.host()),
+    })
+}
+
+/// Build the CORS middleware for the given allowed origins.
+///
+/// # Description
+///
+/// Only `GET` and `POST` requests are allowed from other origins, which is all that
+/// the embeddable signup form needs. When no origin is given, cross-origin requests
+/// are rejected.
+fn cors(allowed_origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST"])
+        .allowed_header(header::CONTENT_TYPE)
+        .max_age(3600);
+
+    allowed_origins.iter().fold(cors, |cors, origin| {
+        if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        }
+    })
+}
+
+/// Apply the migrations of the `migrations/` directory, which are embedded in the
+/// binary.
+///
+/// # Description
+///
+/// The migrator holds a Postgres advisory lock while it runs, so several instances
+/// of the application can start at the same time without racing to apply the same
+/// migrations. Starting a binary that is older than the schema of the DB, i.e. the
+/// DB has migrations applied that the binary doesn't know about, is an error.
+///
+/// Migrations run on a dedicated connection rather than on the pool, so the
+/// `statement_timeout` of the pool doesn't cancel the long ones.
+pub async fn run_migrations(configuration: &DatabaseSettings) -> Result<(), anyhow::Error> {
+    let mut connection = PgConnection::connect_with(&configuration.with_db())
+        .await
+        .context("Failed to connect to the DB to migrate it.")?;
+
+    match sqlx::migrate!("./migrations").run(&mut connection).await {
+        Ok(()) => Ok(()),
+        Err(MigrateError::VersionMissing(version)) => Err(anyhow::anyhow!(
+            "The DB schema is ahead of this binary: migration {version} was applied, but \
+            this version of the application doesn't know about it."
+        )),
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to migrate the DB.")),
+    }
+}
+
+/// Build the pool of DB connections, tuned as set in the configuration.
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
+    let statement_timeout = configuration.statement_timeout_milliseconds;
+
     PgPoolOptions::new()
-        .connect_timeout(std::time::Duration::from_secs(2))
+        .max_connections(configuration.max_connections)
+        .min_connections(configuration.min_connections)
+        .connect_timeout(configuration.acquire_timeout())
+        .idle_timeout(configuration.idle_timeout())
+        .after_connect(move |connection| {
+            Box::pin(async move {
+                if statement_timeout > 0 {
+                    connection
+                        .execute(format!("SET statement_timeout = {statement_timeout}").as_str())
+                        .await?;
+                }
+                Ok(())
+            })
+        })
         .connect_lazy_with(configuration.with_db())
 }
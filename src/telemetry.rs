@@ -1,26 +1,175 @@
+use crate::configuration::LogFormat;
+use hmac::{Hmac, Mac, NewMac};
+use once_cell::sync::Lazy;
+use opentelemetry::global;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::Tracer;
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
 use tracing::subscriber::{set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::{self, MakeWriter};
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
 
+/// Whether [Pii] values are redacted.
+static REDACT_PII: AtomicBool = AtomicBool::new(true);
+
+/// Key of the HMAC that replaces [Pii] values. Until a key is configured, a random
+/// one is used.
+static REDACTION_KEY: Lazy<RwLock<Vec<u8>>> = Lazy::new(|| {
+    let mut key = vec![0; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    RwLock::new(key)
+});
+
+/// Build the subscriber of the tracing stack.
+///
+/// # Description
+///
+/// Spans and events are written to `sink` in the given [LogFormat]. When a [Tracer]
+/// is given, spans are also exported through it to an OpenTelemetry collector.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    format: LogFormat,
+    tracer: Option<Tracer>,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    let formatting_layer = match format {
+        LogFormat::Bunyan => JsonStorageLayer
+            .and_then(BunyanFormattingLayer::new(name, sink))
+            .boxed(),
+        LogFormat::Pretty => fmt::layer().pretty().with_writer(sink).boxed(),
+        LogFormat::Compact => fmt::layer().compact().with_writer(sink).boxed(),
+    };
     Registry::default()
         .with(env_filter)
-        .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
 }
 
+/// Install the subscriber of the tracing stack.
+///
+/// The W3C trace context propagator is installed as well, so the `traceparent` of
+/// incoming requests becomes the parent of their spans.
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger.");
     set_global_default(subscriber).expect("Failed to set subscriber.");
+    global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+/// Get the headers that propagate the trace context of the current span to other
+/// services, e.g. `traceparent`.
+pub fn trace_context_headers() -> HashMap<String, String> {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+
+    headers
+}
+
+/// Enable or disable the redaction of [Pii] values in spans and events.
+///
+/// # Description
+///
+/// Values are redacted with an HMAC keyed with `key`, so they can't be recovered by
+/// hashing guessed values. Without a key, a random one is used, and the redacted
+/// values can only be correlated within the running process.
+pub fn set_pii_redaction(enabled: bool, key: Option<&Secret<String>>) {
+    if let Some(key) = key {
+        *REDACTION_KEY.write().unwrap() = key.expose_secret().as_bytes().to_vec();
+    }
+    REDACT_PII.store(enabled, Ordering::Relaxed);
+}
+
+/// Personally identifiable information that is recorded in a span or an event.
+///
+/// # Description
+///
+/// When redaction is enabled, the value is replaced by a short keyed hash of it, so the
+/// records of the same subscriber can still be correlated without exposing their
+/// details. Otherwise, it's displayed as it is. Use [pii] to wrap a value, e.g.
+/// `tracing::info!(subscriber_email = %pii(&email), "Sending an email")`.
+pub struct Pii<T>(T);
+
+/// Mark a value as [Pii].
+pub fn pii<T: Display>(value: T) -> Pii<T> {
+    Pii(value)
+}
+
+impl<T: Display> Display for Pii<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if REDACT_PII.load(Ordering::Relaxed) {
+            let key = REDACTION_KEY.read().unwrap();
+            f.write_str(&redact(&key, &self.0.to_string()))
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+impl<T: Display> Debug for Pii<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// Replace a value by the first 12 hex digits of its HMAC-SHA256.
+fn redact(key: &[u8], value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length.");
+    mac.update(value.as_bytes());
+    let hash = hex::encode(mac.finalize().into_bytes());
+    format!("redacted:{}", &hash[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pii, redact, set_pii_redaction};
+    use secrecy::Secret;
+
+    const KEY: &[u8] = b"a-local-secret-to-redact-the-logs";
+
+    #[test]
+    fn redacted_values_are_hashed() {
+        let redacted = redact(KEY, "janedoe@mail.com");
+
+        assert!(redacted.starts_with("redacted:"));
+        assert!(!redacted.contains("janedoe"));
+        assert_eq!(redacted, redact(KEY, "janedoe@mail.com"));
+        assert_ne!(redacted, redact(KEY, "johndoe@mail.com"));
+    }
+
+    #[test]
+    fn redacted_values_depend_on_the_key() {
+        assert_ne!(
+            redact(KEY, "janedoe@mail.com"),
+            redact(b"another-secret-to-redact-the-logs", "janedoe@mail.com")
+        );
+    }
+
+    #[test]
+    fn pii_is_only_redacted_when_enabled() {
+        let key = Secret::new(String::from_utf8(KEY.to_vec()).unwrap());
+
+        set_pii_redaction(false, None);
+        assert_eq!(pii("janedoe@mail.com").to_string(), "janedoe@mail.com");
+
+        set_pii_redaction(true, Some(&key));
+        assert_eq!(
+            pii("janedoe@mail.com").to_string(),
+            redact(KEY, "janedoe@mail.com")
+        );
+    }
 }
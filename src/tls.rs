@@ -0,0 +1,267 @@
+//! Module that includes the TLS termination of the HTTP server.
+//!
+//! # Description
+//!
+//! The server can serve HTTPS by itself, for deployments that don't have a proxy in
+//! front of it. The certificate and its private key are read from PEM files, and the
+//! [CertificateResolver] hands the latest pair that was read to every handshake. This
+//! way, the certificate can be renewed while the server runs: [watch_certificate]
+//! reads the files periodically, and swaps the certificate when they change. If the
+//! new files can't be used, e.g. because the certificate was replaced but its
+//! private key wasn't yet, so they don't match, the current certificate is kept
+//! until the next check.
+
+use anyhow::Context;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::{any_supported_type, CertifiedKey};
+use rustls::{Certificate, PrivateKey, ServerConfig, SignatureScheme};
+use rustls_pemfile::Item;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Paths of the PEM files of a certificate chain and its private key.
+#[derive(Clone, Debug)]
+pub struct CertificateFiles {
+    pub certificate_path: PathBuf,
+    pub private_key_path: PathBuf,
+}
+
+impl CertificateFiles {
+    fn read(&self) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+        let read = |path: &Path| {
+            std::fs::read(path).with_context(|| format!("Failed to read {}.", path.display()))
+        };
+
+        Ok((read(&self.certificate_path)?, read(&self.private_key_path)?))
+    }
+}
+
+/// Resolver that hands the current certificate to the TLS handshakes.
+pub struct CertificateResolver {
+    files: CertificateFiles,
+    current: RwLock<Loaded>,
+}
+
+/// The certificate in use, along with the content of the files it was read from.
+struct Loaded {
+    pem: (Vec<u8>, Vec<u8>),
+    certified_key: Arc<CertifiedKey>,
+}
+
+impl CertificateResolver {
+    /// Build a resolver, reading the certificate from the files.
+    pub fn new(files: CertificateFiles) -> Result<Self, anyhow::Error> {
+        let pem = files.read()?;
+        let certified_key = Arc::new(parse_certified_key(&pem.0, &pem.1)?);
+
+        Ok(Self {
+            files,
+            current: RwLock::new(Loaded { pem, certified_key }),
+        })
+    }
+
+    /// Read the files again, and swap the certificate if they changed. Returns
+    /// whether the certificate was swapped.
+    pub fn reload(&self) -> Result<bool, anyhow::Error> {
+        let pem = self.files.read()?;
+        if pem == self.current.read().unwrap().pem {
+            return Ok(false);
+        }
+
+        let certified_key = Arc::new(parse_certified_key(&pem.0, &pem.1)?);
+        *self.current.write().unwrap() = Loaded { pem, certified_key };
+
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().certified_key.clone())
+    }
+}
+
+/// Build the configuration of the TLS server, using the certificate of the resolver.
+pub fn server_config(resolver: Arc<CertificateResolver>) -> ServerConfig {
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver)
+}
+
+/// Reload the certificate of the resolver every `interval`, until the task is
+/// dropped.
+pub async fn watch_certificate(resolver: Arc<CertificateResolver>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        match resolver.reload() {
+            Ok(true) => tracing::info!("The TLS certificate was reloaded"),
+            Ok(false) => {}
+            Err(e) => tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to reload the TLS certificate, the current one is kept"
+            ),
+        }
+    }
+}
+
+fn parse_certified_key(
+    certificate_pem: &[u8],
+    private_key_pem: &[u8],
+) -> Result<CertifiedKey, anyhow::Error> {
+    let certificates: Vec<Certificate> = rustls_pemfile::certs(&mut &certificate_pem[..])
+        .context("Failed to parse the TLS certificate.")?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certificates.is_empty() {
+        anyhow::bail!("The TLS certificate file doesn't include any certificate.");
+    }
+
+    let private_key = rustls_pemfile::read_all(&mut &private_key_pem[..])
+        .context("Failed to parse the TLS private key.")?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .context("The TLS private key file doesn't include any private key.")?;
+    let signing_key = any_supported_type(&private_key)
+        .map_err(|e| anyhow::anyhow!("The TLS private key is not supported: {e}"))?;
+
+    let certified_key = CertifiedKey::new(certificates, signing_key);
+    check_key_matches_certificate(&certified_key)?;
+
+    Ok(certified_key)
+}
+
+/// Check that the private key belongs to the leaf certificate, signing a probe with
+/// the key and verifying the signature with the public key of the certificate.
+fn check_key_matches_certificate(certified_key: &CertifiedKey) -> Result<(), anyhow::Error> {
+    const PROBE: &[u8] = b"newsletter TLS key probe";
+    let schemes: [(SignatureScheme, &webpki::SignatureAlgorithm); 4] = [
+        (
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            &webpki::ECDSA_P256_SHA256,
+        ),
+        (
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            &webpki::ECDSA_P384_SHA384,
+        ),
+        (SignatureScheme::ED25519, &webpki::ED25519),
+        (
+            SignatureScheme::RSA_PSS_SHA256,
+            &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+        ),
+    ];
+
+    let offered: Vec<SignatureScheme> = schemes.iter().map(|(scheme, _)| *scheme).collect();
+    let signer = certified_key
+        .key
+        .choose_scheme(&offered)
+        .context("The TLS private key can't sign with any supported scheme.")?;
+    let (_, algorithm) = schemes
+        .iter()
+        .find(|(scheme, _)| *scheme == signer.scheme())
+        .context("The TLS private key chose a scheme that wasn't offered.")?;
+    let signature = signer
+        .sign(PROBE)
+        .map_err(|e| anyhow::anyhow!("Failed to sign with the TLS private key: {e}"))?;
+
+    let leaf = webpki::EndEntityCert::try_from(certified_key.cert[0].0.as_slice())
+        .map_err(|e| anyhow::anyhow!("Failed to parse the TLS certificate: {e}"))?;
+    leaf.verify_signature(algorithm, PROBE, &signature)
+        .map_err(|_| anyhow::anyhow!("The TLS private key doesn't match the certificate."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CertificateFiles, CertificateResolver};
+    use claim::{assert_err, assert_ok_eq};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+    /// Copy a certificate of the fixtures to a new directory, removed once dropped.
+    fn certificate_files(name: &str) -> (TempDir, CertificateFiles) {
+        let directory = TempDir::new().unwrap();
+        let files = CertificateFiles {
+            certificate_path: directory.path().join("server.crt"),
+            private_key_path: directory.path().join("server.key"),
+        };
+        copy_certificate(name, &files);
+
+        (directory, files)
+    }
+
+    fn copy_certificate(name: &str, files: &CertificateFiles) {
+        let fixture = |extension: &str| PathBuf::from(FIXTURES).join(format!("{name}.{extension}"));
+        std::fs::copy(fixture("crt"), &files.certificate_path).unwrap();
+        std::fs::copy(fixture("key"), &files.private_key_path).unwrap();
+    }
+
+    #[test]
+    fn unchanged_certificates_are_not_reloaded() {
+        let (_directory, files) = certificate_files("first");
+        let resolver = CertificateResolver::new(files).unwrap();
+
+        assert_ok_eq!(resolver.reload(), false);
+    }
+
+    #[test]
+    fn changed_certificates_are_reloaded() {
+        let (_directory, files) = certificate_files("first");
+        let resolver = CertificateResolver::new(files.clone()).unwrap();
+
+        copy_certificate("second", &files);
+
+        assert_ok_eq!(resolver.reload(), true);
+        assert_ok_eq!(resolver.reload(), false);
+    }
+
+    #[test]
+    fn keys_that_dont_match_the_certificate_are_rejected() {
+        let (_directory, files) = certificate_files("first");
+        std::fs::copy(
+            PathBuf::from(FIXTURES).join("second.key"),
+            &files.private_key_path,
+        )
+        .unwrap();
+
+        let error = CertificateResolver::new(files).err().unwrap();
+
+        assert!(error.to_string().contains("doesn't match"), "{error}");
+    }
+
+    #[test]
+    fn certificates_with_a_mismatched_key_are_not_swapped_in() {
+        let (_directory, files) = certificate_files("first");
+        let resolver = CertificateResolver::new(files.clone()).unwrap();
+
+        // Only the certificate was renewed so far.
+        std::fs::copy(
+            PathBuf::from(FIXTURES).join("second.crt"),
+            &files.certificate_path,
+        )
+        .unwrap();
+
+        assert_err!(resolver.reload());
+        copy_certificate("second", &files);
+        assert_ok_eq!(resolver.reload(), true);
+    }
+
+    #[test]
+    fn invalid_certificates_are_not_swapped_in() {
+        let (_directory, files) = certificate_files("first");
+        let resolver = CertificateResolver::new(files.clone()).unwrap();
+
+        std::fs::write(&files.private_key_path, "not a key").unwrap();
+
+        assert_err!(resolver.reload());
+        // The first certificate is still in use, so restoring it isn't a change.
+        copy_certificate("first", &files);
+        assert_ok_eq!(resolver.reload(), false);
+    }
+}
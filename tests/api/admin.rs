@@ -0,0 +1,241 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, newsletter_request_body,
+};
+use newsletter::admin;
+use newsletter::{EmailNormalization, SubscriberEmail};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn email(email: &str) -> SubscriberEmail {
+    SubscriberEmail::parse(email.into()).unwrap()
+}
+
+#[actix_web::test]
+async fn admin_users_are_stored_with_a_hashed_password() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let password = "a-long-enough-password";
+
+    // Test
+    let user_id =
+        admin::create_admin_user(&test_app.db_pool, "admin", Secret::new(password.into()))
+            .await
+            .unwrap();
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT username, password_hash FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.username, "admin");
+    assert!(saved.password_hash.starts_with("$argon2"));
+    assert!(!saved.password_hash.contains(password));
+}
+
+#[actix_web::test]
+async fn admin_users_with_short_passwords_are_rejected() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let outcome =
+        admin::create_admin_user(&test_app.db_pool, "admin", Secret::new("short".into())).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[actix_web::test]
+async fn subscribers_can_be_listed_and_confirmed() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+
+    // Test
+    let pending = admin::list_subscribers(&test_app.db_pool, Some("pending_confirmation"))
+        .await
+        .unwrap();
+    let confirmed = admin::confirm_subscriber(&test_app.db_pool, &email("janedoe@Mail.com"))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].email, "janedoe@mail.com");
+    assert!(confirmed);
+
+    let subscribers = admin::list_subscribers(&test_app.db_pool, Some("confirmed"))
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+
+    let events: serde_json::Value = test_app
+        .get_consent_events("janedoe@mail.com")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(events["events"][1]["kind"], "confirm");
+    assert_eq!(events["events"][1]["source"], "admin_cli");
+}
+
+#[actix_web::test]
+async fn unknown_subscribers_are_neither_confirmed_nor_deleted() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let confirmed = admin::confirm_subscriber(&test_app.db_pool, &email("unknown@mail.com"))
+        .await
+        .unwrap();
+    let deleted = admin::delete_subscriber(&test_app.db_pool, &email("unknown@mail.com"))
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(!confirmed);
+    assert!(!deleted);
+}
+
+#[actix_web::test]
+async fn deleted_subscribers_are_removed() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    // Test
+    let deleted = admin::delete_subscriber(&test_app.db_pool, &email("janedoe@mail.com"))
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(deleted);
+    let subscribers = admin::list_subscribers(&test_app.db_pool, None)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[actix_web::test]
+async fn failed_deliveries_can_be_requeued() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    let failing_mock = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let response: serde_json::Value = test_app
+        .post_newsletters(newsletter_request_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = response["issue_id"].as_str().unwrap();
+    test_app.dispatch_all_pending_emails().await;
+    drop(failing_mock);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let requeued = admin::requeue_failed_deliveries(&test_app.db_pool, None)
+        .await
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(requeued, 1);
+    let report: serde_json::Value = test_app
+        .get_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["failed"], 0);
+}
+
+/// Store a subscriber with the given normalized email.
+async fn insert_subscriber(test_app: &TestApp, email: &str, normalized_email: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, normalized_email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, 'Jane Doe', now(), 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        email,
+        normalized_email,
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn normalized_emails(test_app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT normalized_email FROM subscriptions ORDER BY normalized_email")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.normalized_email)
+        .collect()
+}
+
+#[actix_web::test]
+async fn backfilled_emails_are_normalized_again() {
+    // Prepare
+    let test_app = spawn_app().await;
+    // The migrations backfilled the normalized emails by lowercasing their domain.
+    insert_subscriber(&test_app, "Jane.Doe@Bücher.de", "Jane.Doe@bücher.de").await;
+
+    // Test
+    let report = admin::normalize_emails(&test_app.db_pool, EmailNormalization::default())
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.updated, 1);
+    assert!(report.conflicts.is_empty());
+    assert_eq!(
+        normalized_emails(&test_app).await,
+        vec!["Jane.Doe@xn--bcher-kva.de"]
+    );
+}
+
+#[actix_web::test]
+async fn emails_that_become_duplicates_are_reported() {
+    // Prepare
+    let test_app = spawn_app().await;
+    insert_subscriber(&test_app, "jane.doe@mail.com", "jane.doe@mail.com").await;
+    insert_subscriber(&test_app, "Jane.Doe@mail.com", "Jane.Doe@mail.com").await;
+    let normalization = EmailNormalization {
+        lowercase_local_part: true,
+    };
+
+    // Test
+    let report = admin::normalize_emails(&test_app.db_pool, normalization)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.updated, 0);
+    assert_eq!(report.conflicts, vec!["Jane.Doe@mail.com"]);
+    assert_eq!(
+        normalized_emails(&test_app).await,
+        vec!["Jane.Doe@mail.com", "jane.doe@mail.com"]
+    );
+}
@@ -0,0 +1,108 @@
+use crate::helpers::{spawn_app, TestApp};
+use newsletter::admin;
+use secrecy::Secret;
+use uuid::Uuid;
+
+/// Send a request to every `/admin` endpoint, with the given credentials.
+async fn admin_requests(
+    test_app: &TestApp,
+    credentials: Option<(&str, &str)>,
+) -> Vec<reqwest::Response> {
+    let client = reqwest::Client::new();
+    let requests = [
+        client
+            .post(format!("{}/admin/consent-events", test_app.address))
+            .json(&serde_json::json!({ "subscriber_email": "janedoe@mail.com" })),
+        client
+            .post(format!("{}/admin/suppressions", test_app.address))
+            .json(&serde_json::json!({ "kind": "address", "value": "janedoe@mail.com" })),
+        client
+            .delete(format!("{}/admin/suppressions", test_app.address))
+            .json(&serde_json::json!({ "value": "janedoe@mail.com" })),
+        client.get(format!(
+            "{}/admin/issues/{}/report",
+            test_app.address,
+            Uuid::new_v4()
+        )),
+    ];
+
+    let mut responses = Vec::new();
+    for request in requests {
+        let request = match credentials {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        };
+        responses.push(request.send().await.expect("Failed to execute request."));
+    }
+    responses
+}
+
+#[actix_web::test]
+async fn admin_endpoints_reject_requests_without_credentials() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let responses = admin_requests(&test_app, None).await;
+
+    // Assert
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 401, "{}", response.url());
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Basic realm="admin""#
+        );
+    }
+}
+
+#[actix_web::test]
+async fn admin_endpoints_reject_invalid_credentials() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let username = test_app.test_admin.username.as_str();
+    let test_cases = [
+        ((username, "a-wrong-password"), "a wrong password"),
+        (("unknown-admin", "a-wrong-password"), "an unknown username"),
+    ];
+
+    for (credentials, description) in test_cases {
+        // Test
+        let responses = admin_requests(&test_app, Some(credentials)).await;
+
+        // Assert
+        for response in responses {
+            assert_eq!(
+                response.status().as_u16(),
+                401,
+                "{} was accepted by {}",
+                description,
+                response.url()
+            );
+        }
+    }
+}
+
+#[actix_web::test]
+async fn admins_created_from_the_cli_can_use_the_admin_endpoints() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let password = "a-long-enough-password";
+    admin::create_admin_user(&test_app.db_pool, "admin", Secret::new(password.into()))
+        .await
+        .unwrap();
+
+    // Test
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/issues/{}/report",
+            test_app.address,
+            Uuid::new_v4()
+        ))
+        .basic_auth("admin", Some(password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
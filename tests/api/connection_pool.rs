@@ -0,0 +1,67 @@
+use crate::helpers::spawn_app_with;
+use std::time::{Duration, Instant};
+
+#[actix_web::test]
+async fn connections_use_the_configured_statement_timeout_and_application_name() {
+    // Prepare
+    let test_app = spawn_app_with(|c| {
+        c.database.statement_timeout_milliseconds = 100;
+        c.database.application_name = "newsletter-tests".into();
+    })
+    .await;
+
+    // Test
+    let settings: (String, String) = sqlx::query_as(
+        "SELECT current_setting('statement_timeout'), current_setting('application_name')",
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to query the settings of the connection.");
+    let slow_statement = sqlx::query("SELECT pg_sleep(1)")
+        .execute(&test_app.db_pool)
+        .await;
+
+    // Assert
+    assert_eq!(
+        settings,
+        ("100ms".to_string(), "newsletter-tests".to_string())
+    );
+    let error = slow_statement.expect_err("The slow statement wasn't cancelled.");
+    assert!(error.to_string().contains("statement timeout"), "{error}");
+}
+
+#[actix_web::test]
+async fn acquiring_a_connection_fails_after_the_acquire_timeout() {
+    // Prepare
+    let test_app = spawn_app_with(|c| {
+        c.database.max_connections = 1;
+        // Long enough for the first connection to be opened while the rest of the
+        // tests run.
+        c.database.acquire_timeout_milliseconds = 1000;
+    })
+    .await;
+    let _connection = test_app.db_pool.acquire().await.unwrap();
+
+    // Test
+    let started = Instant::now();
+    let outcome = test_app.db_pool.acquire().await;
+
+    // Assert
+    assert!(outcome.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[actix_web::test]
+async fn readiness_reports_the_configured_max_connections() {
+    // Prepare
+    let test_app = spawn_app_with(|c| c.database.max_connections = 3).await;
+
+    // Test
+    let response = reqwest::get(format!("{}/health/ready", &test_app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["database"]["pool"]["max_connections"], 3);
+}
@@ -0,0 +1,204 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use crate::newsletter::{create_confirmed_subscriber, create_unconfirmed_subscriber};
+use newsletter::consent::{record_consent_event, ConsentContext, ConsentEventKind};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn subscribing_and_confirming_are_recorded_in_the_consent_log() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    // Test
+    let response = test_app.get_consent_events("janedoe@Mail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["subscriber_email"], "janedoe@mail.com");
+
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["kind"], "subscribe");
+    assert_eq!(events[0]["source"], "form_api");
+    assert_eq!(events[0]["ip_address"], "127.0.0.1");
+    assert_eq!(events[1]["kind"], "confirm");
+    assert_eq!(events[1]["source"], "confirmation_link");
+}
+
+#[actix_web::test]
+async fn every_subscription_attempt_is_recorded() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+    create_unconfirmed_subscriber(&test_app).await;
+
+    // Test
+    let response = test_app.get_consent_events("janedoe@mail.com").await;
+
+    // Assert
+    let body: serde_json::Value = response.json().await.unwrap();
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|e| e["kind"] == "subscribe"));
+}
+
+#[actix_web::test]
+async fn following_the_confirmation_link_again_is_not_recorded() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&test_app).await;
+
+    // Test
+    for _ in 0..2 {
+        reqwest::get(confirmation_links.html.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert
+    let response = test_app.get_consent_events("janedoe@mail.com").await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1]["kind"], "confirm");
+}
+
+#[actix_web::test]
+async fn unsubscriptions_and_preference_changes_can_be_recorded() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let context = ConsentContext {
+        ip_address: None,
+        user_agent: None,
+    };
+
+    // Test
+    for kind in [
+        ConsentEventKind::PreferencesChange,
+        ConsentEventKind::Unsubscribe,
+    ] {
+        record_consent_event(&test_app.db_pool, subscriber_id, kind, "test", &context)
+            .await
+            .unwrap();
+    }
+
+    // Assert
+    let response = test_app.get_consent_events("janedoe@mail.com").await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[2]["kind"], "preferences_change");
+    assert_eq!(events[3]["kind"], "unsubscribe");
+}
+
+#[actix_web::test]
+async fn consent_events_cannot_be_modified() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+
+    // Test
+    let outcome = sqlx::query!("UPDATE consent_events SET kind = 'confirm'")
+        .execute(&test_app.db_pool)
+        .await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[actix_web::test]
+async fn consent_events_cannot_be_deleted() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+
+    // Test
+    let outcome = sqlx::query!("DELETE FROM consent_events")
+        .execute(&test_app.db_pool)
+        .await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[actix_web::test]
+async fn consent_events_are_deleted_along_with_their_subscriber() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+
+    // Test
+    sqlx::query!("DELETE FROM subscriptions")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Assert
+    let events = sqlx::query!("SELECT consent_event_id FROM consent_events")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+/// Subscribe `janedoe@mail.com` with a forged `X-Forwarded-For` header.
+async fn subscribe_with_forwarded_for(address: &str, forwarded_for: &str) {
+    reqwest::Client::new()
+        .post(format!("{address}/subscriptions"))
+        .header("X-Forwarded-For", forwarded_for)
+        .json(&serde_json::json!({"name": "Jane Doe", "email": "janedoe@mail.com"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn forwarded_addresses_are_only_recorded_from_trusted_proxies() {
+    for (trusted_proxies, expected) in [
+        (Vec::new(), "127.0.0.1"),
+        (vec!["127.0.0.1".parse().unwrap()], "198.51.100.1"),
+    ] {
+        // Prepare
+        let test_app = spawn_app_with(|c| c.application.trusted_proxies = trusted_proxies).await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&test_app.email_server)
+            .await;
+
+        // Test
+        subscribe_with_forwarded_for(&test_app.address, "198.51.100.1").await;
+
+        // Assert
+        let body: serde_json::Value = test_app
+            .get_consent_events("janedoe@mail.com")
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["events"][0]["ip_address"], expected);
+    }
+}
+
+#[actix_web::test]
+async fn consent_log_returns_404_for_unknown_subscribers() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = test_app.get_consent_events("nobody@mail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -1,4 +1,8 @@
 use crate::helpers::spawn_app;
+use newsletter::configuration::get_configuration;
+use sqlx::{Connection, Executor, PgConnection};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
 
 /// Server health check test case.
 ///
@@ -21,3 +25,80 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[actix_web::test]
+async fn readiness_reports_healthy_dependencies() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::get(format!("{}/health/ready", &test_app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"]["status"], "healthy");
+    assert!(body["checks"]["database"]["pool"]["max_connections"].is_u64());
+    assert_eq!(body["checks"]["email_provider"]["status"], "unknown");
+}
+
+#[actix_web::test]
+async fn readiness_reports_the_last_success_of_the_email_provider() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=jane%20doe&email=janedoe%40mail.com".into())
+        .await;
+
+    // Test
+    let response = reqwest::get(format!("{}/health/ready", &test_app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "healthy");
+    assert!(body["checks"]["email_provider"]["last_success"].is_string());
+}
+
+#[actix_web::test]
+async fn readiness_returns_503_when_the_database_is_down() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let database_name: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    test_app.db_pool.close().await;
+
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let mut connection = PgConnection::connect_with(&configuration.database.without_db())
+        .await
+        .expect("Failed to connect to Postgres.");
+    connection
+        .execute(format!(r#"DROP DATABASE "{database_name}" WITH (FORCE);"#).as_str())
+        .await
+        .expect("Failed to drop the database.");
+
+    // Test
+    let response = reqwest::get(format!("{}/health/ready", &test_app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["database"]["status"], "unhealthy");
+    assert!(body["checks"]["database"]["error"].is_string());
+}
@@ -1,9 +1,18 @@
+use actix_web::dev::ServerHandle;
 use actix_web::rt::spawn;
-use newsletter::configuration::{get_configuration, DatabaseSettings};
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use newsletter::configuration::{get_configuration, DatabaseSettings, LogFormat, Settings};
+use newsletter::email_client::EmailClient;
+use newsletter::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
 use newsletter::startup::get_connection_pool;
 use newsletter::startup::Application;
 use newsletter::telemetry::{get_subscriber, init_subscriber};
+use newsletter::EmailNormalization;
 use once_cell::sync::Lazy;
+use opentelemetry::global;
+use opentelemetry::sdk::trace::TracerProvider;
+use opentelemetry::trace::TracerProvider as _;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
@@ -15,30 +24,62 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     // We cannot assign the output of `get_subscriber` to a variable based on the value of `TEST_LOG`
     // because the sink is part of the type returned by `get_subscriber`, therefore they are not the
     // same type. We could work around it, but this is the most straight-forward way of moving forward.
+    // Spans aren't exported anywhere, but they still get a trace context, so its
+    // propagation can be tested.
+    let provider = TracerProvider::builder().build();
+    let tracer = provider.tracer("test");
+    global::set_tracer_provider(provider);
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            LogFormat::Bunyan,
+            Some(tracer),
+        );
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            LogFormat::Bunyan,
+            Some(tracer),
+        );
         init_subscriber(subscriber);
     };
 });
 
+/// Origin that is allowed to send cross-origin requests to the test application.
+pub const ALLOWED_ORIGIN: &str = "https://www.example.com";
+
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
 }
+/// Links included in the email that answers a privacy request.
+pub struct PrivacyLinks {
+    pub export: reqwest::Url,
+    pub erasure: reqwest::Url,
+}
+
 pub struct TestApp {
     pub address: String,
     pub port: u16,
     pub db_pool: PgPool,
+    pub database_name: String,
     pub email_server: MockServer,
+    pub email_client: EmailClient,
+    pub email_normalization: EmailNormalization,
+    pub server_handle: ServerHandle,
+    pub redirect_port: Option<u16>,
+    pub test_admin: TestAdmin,
 }
 
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -46,6 +87,30 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_with_language(
+        &self,
+        body: String,
+        accept_language: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept-Language", accept_language)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_configuration_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -65,26 +130,195 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
 
+    pub fn get_privacy_links(&self, email_request: &wiremock::Request) -> PrivacyLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| {
+                let mut link = reqwest::Url::parse(l.as_str()).unwrap();
+                link.set_port(Some(self.port)).unwrap();
+                // Let's check we won't call random APIs on the web.
+                assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+                link
+            })
+            .collect();
+        assert_eq!(links.len(), 2);
+
+        PrivacyLinks {
+            export: links[0].clone(),
+            erasure: links[1].clone(),
+        }
+    }
+
+    pub async fn post_privacy_request(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/privacy", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Confirm the erasure of the data of a subscriber, posting the privacy token of
+    /// the erasure link as the confirmation page does.
+    pub async fn post_erasure(&self, erasure_link: &reqwest::Url) -> reqwest::Response {
+        let privacy_token = erasure_link
+            .query_pairs()
+            .find(|(key, _)| key == "privacy_token")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/privacy/erase", &self.address))
+            .form(&[("privacy_token", privacy_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_consent_events(&self, email: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/consent-events", &self.address))
+            .basic_auth(&self.test_admin.username, Some(&self.test_admin.password))
+            .json(&serde_json::json!({ "subscriber_email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             .json(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
+
+    pub async fn get_issue_report(&self, issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/issues/{}/report",
+                &self.address, issue_id
+            ))
+            .basic_auth(&self.test_admin.username, Some(&self.test_admin.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Run the issue delivery worker until the queue is empty.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db_pool, &self.email_client, self.email_normalization)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn post_suppressions(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/suppressions", &self.address))
+            .basic_auth(&self.test_admin.username, Some(&self.test_admin.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_suppression(&self, value: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/suppressions", &self.address))
+            .basic_auth(&self.test_admin.username, Some(&self.test_admin.password))
+            .json(&serde_json::json!({ "value": value }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
+/// Administrator whose credentials are sent to the `/admin` endpoints.
+pub struct TestAdmin {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestAdmin {
+    fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        // Cheap parameters keep the tests fast, the hash carries them along.
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(Params::MIN_M_COST, 1, 1, None).unwrap(),
+        )
+        .hash_password(self.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO users (user_id, username, password_hash, created_at)
+            VALUES ($1, $2, $3, now())
+            "#,
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store the test admin.");
+    }
 }
 
 /// Helper function that sets up a server and binds it to an address that is
 /// returned. This way, individual tests know where to send their requests.
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| ()).await
+}
+
+/// Spawn the application, customizing its configuration beforehand.
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
+    spawn_app_on(None, customize).await
+}
+
+/// Spawn another instance of the application that uses the database of `test_app`,
+/// to check what happens when the configuration changes between two deployments.
+pub async fn spawn_app_sharing_database(
+    test_app: &TestApp,
+    customize: impl FnOnce(&mut Settings),
+) -> TestApp {
+    spawn_app_on(Some(test_app.database_name.clone()), customize).await
+}
+
+/// Spawn the application on a new database, or on an existing one.
+async fn spawn_app_on(
+    database_name: Option<String>,
+    customize: impl FnOnce(&mut Settings),
+) -> TestApp {
     Lazy::force(&TRACING);
+    let new_database = database_name.is_none();
 
     // Launch a mock server in lieu of Postmark's API.
     let email_server = MockServer::start().await;
@@ -92,16 +326,25 @@ pub async fn spawn_app() -> TestApp {
     // Randomise configuration to ensure test isolation.
     let configuration = {
         let mut c = get_configuration().expect("Failed to read configuration");
-        c.database.database_name = Uuid::new_v4().to_string();
+        c.database.database_name = database_name.unwrap_or_else(|| Uuid::new_v4().to_string());
         // Use a random OS port
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Allow a fake marketing site to embed the signup form
+        c.application.cors_allowed_origins = vec![ALLOWED_ORIGIN.into()];
+        customize(&mut c);
         c
     };
 
-    // Create and migrate the DB.
-    configure_database(&configuration.database).await;
+    // Create and migrate a new DB, unless the application migrates it at startup.
+    if new_database {
+        if configuration.database.migrate_on_startup {
+            create_database(&configuration.database).await;
+        } else {
+            configure_database(&configuration.database).await;
+        }
+    }
 
     let application = Application::build(configuration.clone())
         .await
@@ -109,26 +352,31 @@ pub async fn spawn_app() -> TestApp {
 
     let port = application.port();
     let address = format!("http://127.0.0.1:{port}");
+    let server_handle = application.handle();
+    let redirect_port = application.redirect_port();
+
+    spawn(application.run_until_stopped());
 
-    let _ = spawn(application.run_until_stopped());
+    let db_pool = get_connection_pool(&configuration.database);
+    let test_admin = TestAdmin::generate();
+    test_admin.store(&db_pool).await;
 
     TestApp {
         address,
         port,
-        db_pool: get_connection_pool(&configuration.database),
+        db_pool,
+        database_name: configuration.database.database_name.clone(),
         email_server,
+        email_client: configuration.email_client.client(),
+        email_normalization: configuration.email_verification.normalization(),
+        server_handle,
+        redirect_port,
+        test_admin,
     }
 }
 
 pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
-    // Connect DB
-    let mut connection = PgConnection::connect_with(&config.without_db())
-        .await
-        .expect("Failed to connect to Postgres.");
-    connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
-        .await
-        .expect("Failed to create database.");
+    create_database(config).await;
 
     // Migrate DB
     let connection_pool = PgPool::connect_with(config.with_db())
@@ -141,3 +389,14 @@ pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
 
     connection_pool
 }
+
+pub async fn create_database(config: &DatabaseSettings) {
+    // Connect DB
+    let mut connection = PgConnection::connect_with(&config.without_db())
+        .await
+        .expect("Failed to connect to Postgres.");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database.");
+}
@@ -1,5 +1,16 @@
+mod admin;
+mod authentication;
+mod connection_pool;
+mod consent_events;
 mod health_check;
 mod helpers;
+mod metrics;
+mod migrations;
 mod newsletter;
+mod shutdown;
+mod subscribe_form;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_privacy;
+mod suppressions;
+mod tls;
@@ -0,0 +1,82 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn metrics_are_exposed_in_the_prometheus_format() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=jane%20doe&email=janedoe%40mail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Test
+    let response = reqwest::get(format!("{}/metrics", &test_app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains(r#"http_requests_total{method="POST",route="/subscriptions",status="200"}"#)
+    );
+    assert!(body.contains("http_request_duration_seconds_bucket"));
+    assert!(body.contains("newsletter_subscriptions_total"));
+    assert!(body.contains(r#"email_sends_total{outcome="success"}"#));
+    assert!(body.contains("email_send_duration_seconds_count"));
+    assert!(body.contains("db_pool_max_connections"));
+}
+
+#[actix_web::test]
+async fn requests_to_unknown_paths_are_not_labelled_with_their_path() {
+    // Prepare
+    let test_app = spawn_app().await;
+    reqwest::get(format!("{}/not/a/route/12345", &test_app.address))
+        .await
+        .unwrap();
+
+    // Test
+    let body = reqwest::get(format!("{}/metrics", &test_app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(body.contains(r#"route="unmatched",status="404""#));
+    assert!(!body.contains("/not/a/route/12345"));
+}
+
+#[actix_web::test]
+async fn requests_with_non_standard_methods_are_labelled_as_other() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let method = reqwest::Method::from_bytes(b"PURGE-12345").unwrap();
+    reqwest::Client::new()
+        .request(method, format!("{}/health_check", &test_app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Test
+    let body = reqwest::get(format!("{}/metrics", &test_app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(body.contains(r#"method="other""#));
+    assert!(!body.contains("PURGE-12345"));
+}
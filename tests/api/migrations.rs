@@ -0,0 +1,85 @@
+use crate::helpers::{configure_database, spawn_app_with};
+use newsletter::configuration::get_configuration;
+use newsletter::startup::Application;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn the_application_migrates_the_database_at_startup_if_enabled() {
+    // Prepare
+    let test_app = spawn_app_with(|c| c.database.migrate_on_startup = true).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    // `_sqlx_migrations` is created by the migrator, so the query can't be checked
+    // at compile time.
+    let migrated: i64 = sqlx::query_scalar("SELECT count(*) FROM _sqlx_migrations")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to query the applied migrations.");
+
+    // Assert
+    assert!(migrated > 0);
+    let response = test_app
+        .post_subscriptions("name=jane%20doe&email=janedoe%40mail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn migrations_are_not_cancelled_by_the_statement_timeout() {
+    // Prepare
+    let test_app = spawn_app_with(|c| {
+        c.database.migrate_on_startup = true;
+        c.database.statement_timeout_milliseconds = 1;
+    })
+    .await;
+
+    // Test
+    // The connections of the test pool have the timeout too.
+    let mut transaction = test_app.db_pool.begin().await.unwrap();
+    sqlx::query("SET LOCAL statement_timeout = 0")
+        .execute(&mut transaction)
+        .await
+        .unwrap();
+    let migrated: i64 = sqlx::query_scalar("SELECT count(*) FROM _sqlx_migrations")
+        .fetch_one(&mut transaction)
+        .await
+        .expect("Failed to query the applied migrations.");
+
+    // Assert
+    assert!(migrated > 0);
+}
+
+#[actix_web::test]
+async fn the_application_fails_to_start_if_the_schema_is_ahead_of_it() {
+    // Prepare
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    configuration.database.migrate_on_startup = true;
+    configuration.application.port = 0;
+    let pool = configure_database(&configuration.database).await;
+
+    sqlx::query(
+        r#"
+        INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99990101000000, 'from a newer release', true, '\x00', 0)
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to record a newer migration.");
+
+    // Test
+    let outcome = Application::build(configuration).await;
+
+    // Assert
+    let error = outcome.err().expect("The application started.");
+    assert!(error.to_string().contains("ahead of this binary"));
+}
@@ -1,7 +1,7 @@
 //! tests/api/newsletter.rs
 
 use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
-use wiremock::matchers::{any, method, path};
+use wiremock::matchers::{any, body_partial_json, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 #[actix_web::test]
@@ -30,11 +30,19 @@ async fn newsletter_are_not_delivered_to_unconfirmed_subscribers() {
 
     // Assert
     assert_eq!(response.status().as_u16(), 200);
+    test_app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we haven't sent the newsletter email
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=Jane%20Doe&email=janedoe%40mail.com";
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with_email(app, "janedoe@mail.com").await
+}
+
+pub async fn create_unconfirmed_subscriber_with_email(
+    app: &TestApp,
+    email: &str,
+) -> ConfirmationLinks {
+    let body = serde_urlencoded::to_string([("name", "Jane Doe"), ("email", email)]).unwrap();
 
     let _mock_guard = Mock::given(path("/email"))
         .and(method("POST"))
@@ -44,7 +52,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .mount_as_scoped(&app.email_server)
         .await;
 
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -57,11 +65,15 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .pop()
         .unwrap();
 
-    app.get_configuration_links(&email_request)
+    app.get_configuration_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    create_confirmed_subscriber_with_email(app, "janedoe@mail.com").await
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
+pub async fn create_confirmed_subscriber_with_email(app: &TestApp, email: &str) {
+    let confirmation_link = create_unconfirmed_subscriber_with_email(app, email).await;
     reqwest::get(confirmation_link.html)
         .await
         .unwrap()
@@ -95,6 +107,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
 
     // Assert
     assert_eq!(response.status().as_u16(), 200);
+    test_app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we have sent the newsletter email
 }
 
@@ -131,3 +144,326 @@ async fn newsletters_returns_400_for_invalid_data() {
         );
     }
 }
+
+pub fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[actix_web::test]
+async fn delivery_report_counts_the_deliveries_of_an_issue() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&test_app, "jane@mail.com").await;
+    create_confirmed_subscriber_with_email(&test_app, "john@mail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response: serde_json::Value = test_app
+        .post_newsletters(newsletter_request_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = response["issue_id"].as_str().unwrap();
+
+    // Test: the issue is still queued.
+    let report: serde_json::Value = test_app
+        .get_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(report["total"], 2);
+    assert_eq!(report["queued"], 2);
+    assert_eq!(report["sent"], 0);
+
+    // Test: the issue was sent.
+    test_app.dispatch_all_pending_emails().await;
+    let report: serde_json::Value = test_app
+        .get_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(report["title"], "Newsletter title");
+    assert_eq!(report["total"], 2);
+    assert_eq!(report["queued"], 0);
+    assert_eq!(report["sent"], 2);
+    assert_eq!(report["failures"].as_array().unwrap().len(), 0);
+}
+
+#[actix_web::test]
+async fn failed_deliveries_are_reported_and_do_not_stop_the_send() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&test_app, "jane@mail.com").await;
+    create_confirmed_subscriber_with_email(&test_app, "john@mail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({"To": "jane@mail.com"}),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"MessageID": "id-1"})),
+        )
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response: serde_json::Value = test_app
+        .post_newsletters(newsletter_request_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = response["issue_id"].as_str().unwrap();
+
+    // Test
+    test_app.dispatch_all_pending_emails().await;
+    let report: serde_json::Value = test_app
+        .get_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["failures"][0]["subscriber_email"], "jane@mail.com");
+    assert!(report["failures"][0]["reason"].is_string());
+
+    let saved = sqlx::query!(
+        "SELECT provider_message_id FROM issue_deliveries WHERE subscriber_email = $1",
+        "john@mail.com",
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.provider_message_id.as_deref(), Some("id-1"));
+}
+
+#[actix_web::test]
+async fn delivery_report_returns_404_for_unknown_issues() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = test_app
+        .get_issue_report(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn newsletters_returns_400_for_empty_or_too_long_fields() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "title": " ",
+                "content": {"text": "Plain text", "html": "<p>HTML</p>"}
+            }),
+            "empty title",
+        ),
+        (
+            serde_json::json!({
+                "title": "a".repeat(257),
+                "content": {"text": "Plain text", "html": "<p>HTML</p>"}
+            }),
+            "too long title",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": {"text": "", "html": "<p>HTML</p>"}
+            }),
+            "empty plain text content",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": {"text": "Plain text", "html": ""}
+            }),
+            "empty HTML content",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = test_app.post_newsletters(invalid_body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message,
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Invalid newsletter issue.");
+        assert_eq!(body["details"].as_array().unwrap().len(), 1);
+    }
+}
+
+#[actix_web::test]
+async fn newsletters_reports_every_problem_of_the_body() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let invalid_body = serde_json::json!({
+        "title": "",
+        "content": {"text": "", "html": ""}
+    });
+
+    // Test
+    let response = test_app.post_newsletters(invalid_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["details"].as_array().unwrap().len(), 3);
+}
+
+#[actix_web::test]
+async fn newsletters_returns_a_json_error_for_malformed_bodies() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &test_app.address))
+        .header("Content-Type", "application/json")
+        .body("{\"title\": ")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+    assert_eq!(body["details"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn newsletters_are_delivered_in_the_locale_of_each_subscriber() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&test_app, "jane@mail.com").await;
+    create_confirmed_subscriber_with_email(&test_app, "juana@mail.com").await;
+    sqlx::query!("UPDATE subscriptions SET locale = 'es' WHERE email = 'juana@mail.com'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(body_partial_json(serde_json::json!({
+            "To": "jane@mail.com",
+            "Subject": "Newsletter title",
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(body_partial_json(serde_json::json!({
+            "To": "juana@mail.com",
+            "Subject": "Título del boletín",
+            "TextBody": "Cuerpo del boletín",
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "variants": {
+                "es": {
+                    "title": "Título del boletín",
+                    "content": {
+                        "text": "Cuerpo del boletín",
+                        "html": "<p>Cuerpo del boletín</p>",
+                    }
+                },
+                "fr": {
+                    "title": "Titre de la newsletter",
+                    "content": {
+                        "text": "Corps de la newsletter",
+                        "html": "<p>Corps de la newsletter</p>",
+                    }
+                }
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn newsletters_returns_400_for_invalid_variants() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "variants": {
+                "ja": {
+                    "title": "Title",
+                    "content": {"text": "Body", "html": "<p>Body</p>"}
+                },
+                "es": {
+                    "title": "",
+                    "content": {"text": "Cuerpo", "html": "<p>Cuerpo</p>"}
+                }
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["details"],
+        serde_json::json!([
+            "The es variant: The title is empty.",
+            "The locale \"ja\" is not supported.",
+        ])
+    );
+}
@@ -0,0 +1,113 @@
+use crate::helpers::spawn_app;
+use crate::newsletter::{create_confirmed_subscriber_with_email, newsletter_request_body};
+use newsletter::issue_delivery_worker::worker_loop;
+use std::time::Duration;
+use tokio::sync::watch;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn in_flight_requests_finish_when_the_server_is_stopped() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=Jane%20Doe&email=janedoe%40mail.com".to_string();
+
+    // Test: the server is stopped while the subscription is sending its email.
+    let (response, _) = tokio::join!(test_app.post_subscriptions(body), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        test_app.server_handle.stop(true).await;
+    });
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    // New connections are refused once the server has stopped.
+    let outcome = reqwest::get(format!("{}/health_check", &test_app.address)).await;
+    assert!(outcome.is_err());
+}
+
+#[actix_web::test]
+async fn idle_worker_stops_when_shutdown_is_signalled() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+    // Test
+    let worker = worker_loop(
+        &test_app.db_pool,
+        &test_app.email_client,
+        test_app.email_normalization,
+        shutdown_receiver,
+    );
+    let (outcome, _) = tokio::join!(
+        tokio::time::timeout(Duration::from_secs(2), worker),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            shutdown_sender.send(true).unwrap();
+        }
+    );
+
+    // Assert: the worker didn't wait for its next poll of the queue.
+    assert!(outcome.is_ok());
+}
+
+#[actix_web::test]
+async fn worker_finishes_the_current_email_when_shutdown_is_signalled() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&test_app, "jane@mail.com").await;
+    create_confirmed_subscriber_with_email(&test_app, "john@mail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response: serde_json::Value = test_app
+        .post_newsletters(newsletter_request_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = response["issue_id"].as_str().unwrap();
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+    // Test: shutdown is signalled while the first email is being sent.
+    tokio::join!(
+        worker_loop(
+            &test_app.db_pool,
+            &test_app.email_client,
+            test_app.email_normalization,
+            shutdown_receiver,
+        ),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            shutdown_sender.send(true).unwrap();
+        }
+    );
+
+    // Assert: the current email was delivered, and the next one is still queued.
+    let report: serde_json::Value = test_app
+        .get_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["queued"], 1);
+}
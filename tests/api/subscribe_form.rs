@@ -0,0 +1,278 @@
+//! tests/api/subscribe_form.rs
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp, ALLOWED_ORIGIN};
+use newsletter::configuration::{get_configuration, CaptchaSettings};
+use newsletter::signed_token::SigningKey;
+use secrecy::Secret;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+/// Spawn an app that checks the minimum time to submit the signup form.
+async fn spawn_app_with_min_submit_time() -> TestApp {
+    spawn_app_with(|c| c.bot_protection.min_submit_seconds = 3).await
+}
+
+/// Sign a render time with the key of the configuration, as the signup form does.
+fn render_token(rendered_at: i64) -> String {
+    let secret = get_configuration()
+        .unwrap()
+        .bot_protection
+        .form_signing_key
+        .unwrap();
+    let key = SigningKey::new("signup-form".into(), secret).unwrap();
+    let rendered_at = rendered_at.to_string();
+
+    format!("{rendered_at}.{}", key.sign(&rendered_at))
+}
+
+#[actix_web::test]
+async fn subscribe_form_is_served_as_html() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::get(&format!("{}/subscribe/form", test_app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains("action=\"http://127.0.0.1/subscriptions\""));
+    assert!(body.contains("name=\"website\""));
+    assert!(body.contains("name=\"rendered_at\""));
+}
+
+#[actix_web::test]
+async fn subscribe_form_renders_the_captcha_widget_when_enabled() {
+    // Prepare
+    let test_app = spawn_app_with(|c| {
+        c.bot_protection.captcha = Some(CaptchaSettings {
+            base_url: "http://127.0.0.1".into(),
+            secret_key: Secret::new("a-secret-key".into()),
+            site_key: "a-site-key".into(),
+            script_url: "https://js.hcaptcha.com/1/api.js".into(),
+            widget_class: "h-captcha".into(),
+            timeout_milliseconds: 200,
+        })
+    })
+    .await;
+
+    // Test
+    let response = reqwest::get(&format!("{}/subscribe/form", test_app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"<script src="https://js.hcaptcha.com/1/api.js""#));
+    assert!(body.contains(r#"<div class="h-captcha" data-sitekey="a-site-key">"#));
+}
+
+#[actix_web::test]
+async fn subscriptions_filling_the_honeypot_are_discarded() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = "name=Jane%20Doe&email=janedoe%40mail.com&website=http%3A%2F%2Fspam.com";
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn cross_origin_requests_are_allowed_for_configured_origins() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/subscriptions", test_app.address),
+        )
+        .header("Origin", ALLOWED_ORIGIN)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "content-type")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        ALLOWED_ORIGIN
+    );
+}
+
+#[actix_web::test]
+async fn cross_origin_requests_are_rejected_for_unknown_origins() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/subscriptions", test_app.address),
+        )
+        .header("Origin", "https://www.unknown.com")
+        .header("Access-Control-Request-Method", "POST")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[actix_web::test]
+async fn subscriptions_submitted_too_fast_are_discarded() {
+    // Prepare
+    let test_app = spawn_app_with_min_submit_time().await;
+    let form = reqwest::get(&format!("{}/subscribe/form", test_app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let rendered_at = form
+        .split(r#"name="rendered_at" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let body = serde_urlencoded::to_string([
+        ("name", "Jane Doe"),
+        ("email", "janedoe@mail.com"),
+        ("rendered_at", rendered_at),
+    ])
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn subscriptions_without_a_signed_render_time_are_discarded() {
+    // Prepare
+    let test_app = spawn_app_with_min_submit_time().await;
+    let an_hour_ago = (chrono::Utc::now().timestamp() - 3600).to_string();
+    let forged =
+        render_token(chrono::Utc::now().timestamp()).replacen(|c: char| c.is_ascii_digit(), "0", 1);
+    let test_cases = [
+        (None, "no render time"),
+        (Some(an_hour_ago.as_str()), "an unsigned render time"),
+        (Some(forged.as_str()), "a forged render time"),
+    ];
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    for (rendered_at, description) in test_cases {
+        let mut body = serde_json::json!({"name": "Jane Doe", "email": "janedoe@mail.com"});
+        if let Some(rendered_at) = rendered_at {
+            body["rendered_at"] = rendered_at.into();
+        }
+
+        // Test
+        let response = test_app.post_subscriptions_json(body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "The response was not 200 with {description}."
+        );
+    }
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn subscriptions_submitted_after_the_minimum_time_are_accepted() {
+    // Prepare
+    let test_app = spawn_app_with_min_submit_time().await;
+    let body = serde_json::json!({
+        "name": "Jane Doe",
+        "email": "janedoe@mail.com",
+        "website": "",
+        "rendered_at": render_token(chrono::Utc::now().timestamp() - 60),
+    });
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions_json(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn subscriptions_without_a_render_time_are_accepted_by_default() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = serde_json::json!({"name": "Jane Doe", "email": "janedoe@mail.com"});
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions_json(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "janedoe@mail.com");
+}
@@ -1,6 +1,6 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
 use uuid::Uuid;
-use wiremock::matchers::{method, path};
+use wiremock::matchers::{body_partial_json, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 #[actix_web::test]
@@ -87,6 +87,9 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
             "The API did not return a 400 Bad Request when the payload was {}",
             description
         );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Invalid subscriber data.");
+        assert_eq!(body["details"].as_array().unwrap().len(), 1);
     }
 }
 
@@ -126,7 +129,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // Check
     let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = test_app.get_configuration_links(&email_request);
+    let confirmation_links = test_app.get_configuration_links(email_request);
 
     // Both links must be identical.
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
@@ -139,7 +142,7 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     let body = "name=Jane%20Doe&email=janedoe%40mail.com";
 
     // Sabotage the database
-    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;",)
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token_hash;",)
         .execute(&test_app.db_pool)
         .await
         .unwrap();
@@ -171,14 +174,14 @@ async fn subscribe_fails_if_second_subscription_attempt_returns_the_same_token()
     };
 
     let result = sqlx::query!(
-        "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        "SELECT subscription_token_hash FROM subscription_tokens WHERE subscriber_id = $1",
         subscriber_id,
     )
     .fetch_optional(&test_app.db_pool)
     .await;
 
     let first_subscriber_token = match result {
-        Ok(record) => record.unwrap().subscription_token,
+        Ok(record) => record.unwrap().subscription_token_hash,
         Err(e) => panic!("Failed to execute query: {:?}", e),
     };
 
@@ -186,16 +189,366 @@ async fn subscribe_fails_if_second_subscription_attempt_returns_the_same_token()
     test_app.post_subscriptions(body.into()).await;
 
     let result = sqlx::query!(
-        "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        "SELECT subscription_token_hash FROM subscription_tokens WHERE subscriber_id = $1",
         subscriber_id,
     )
     .fetch_optional(&test_app.db_pool)
     .await;
 
     let sec_subscriber_token = match result {
-        Ok(record) => record.unwrap().subscription_token,
+        Ok(record) => record.unwrap().subscription_token_hash,
         Err(e) => panic!("Failed to execute query: {:?}", e),
     };
 
     assert_ne!(first_subscriber_token, sec_subscriber_token);
 }
+
+#[actix_web::test]
+async fn subscribe_returns_the_subscription_status_as_json() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = "name=jane%20doe&email=jane_doe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_accepts_json_bodies() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = serde_json::json!({"name": "jane doe", "email": "jane_doe@mail.com"});
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions_json(body).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+
+    assert_eq!(saved.email, "jane_doe@mail.com");
+    assert_eq!(saved.name, "jane doe");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_when_json_data_is_missing_or_invalid() {
+    let test_app = spawn_app().await;
+
+    let test_cases = vec![
+        (serde_json::json!({"name": "jane doe"}), "missing the email"),
+        (
+            serde_json::json!({"email": "jane_doe@mail.com"}),
+            "missing the name",
+        ),
+        (
+            serde_json::json!({"name": "jane doe", "email": "not-an-email"}),
+            "invalid email",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = test_app.post_subscriptions_json(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_415_for_unsupported_content_types() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "text/plain")
+        .body("jane doe <jane_doe@mail.com>")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(415, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_for_disposable_email_domains() {
+    let test_app = spawn_app().await;
+    let body = "name=jane%20doe&email=jane_doe%40mailinator.com";
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn subscribe_suggests_a_domain_for_likely_typos() {
+    let test_app = spawn_app().await;
+    let body = "name=jane%20doe&email=jane_doe%40gmial.com";
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["details"][0]
+        .as_str()
+        .unwrap()
+        .contains("Did you mean gmail.com?"));
+}
+
+/// Subscribe twice with emails that only differ in their case, returning the stored
+/// emails and their normalized forms.
+async fn subscribe_twice(test_app: &TestApp, first: &str, second: &str) -> Vec<(String, String)> {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    for email in [first, second] {
+        test_app
+            .post_subscriptions(format!(
+                "name=Jane%20Doe&email={}",
+                email.replace('@', "%40")
+            ))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    sqlx::query!("SELECT email, normalized_email FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions")
+        .into_iter()
+        .map(|s| (s.email, s.normalized_email))
+        .collect()
+}
+
+#[actix_web::test]
+async fn subscribe_treats_emails_differing_in_the_case_of_their_domain_as_the_same_subscriber() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let saved = subscribe_twice(&test_app, "Jane.Doe@Mail.com", "Jane.Doe@mail.com").await;
+
+    // Assert
+    assert_eq!(
+        saved,
+        vec![("Jane.Doe@Mail.com".into(), "Jane.Doe@mail.com".into())]
+    );
+}
+
+#[actix_web::test]
+async fn subscribe_keeps_the_case_of_the_local_part_by_default() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let saved = subscribe_twice(&test_app, "Jane.Doe@mail.com", "jane.doe@mail.com").await;
+
+    // Assert
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].1, "Jane.Doe@mail.com");
+    assert_eq!(saved[1].1, "jane.doe@mail.com");
+}
+
+#[actix_web::test]
+async fn subscribe_ignores_the_case_of_the_local_part_when_configured() {
+    // Prepare
+    let test_app = spawn_app_with(|c| c.email_verification.lowercase_local_part = true).await;
+
+    // Test
+    let saved = subscribe_twice(&test_app, "Jane.Doe@Mail.com", "jane.doe@mail.com").await;
+
+    // Assert
+    assert_eq!(
+        saved,
+        vec![("Jane.Doe@Mail.com".into(), "jane.doe@mail.com".into())]
+    );
+}
+
+#[actix_web::test]
+async fn subscribe_sends_the_confirmation_email_in_the_accepted_language() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({"Subject": "¡Bienvenido!"}),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    test_app
+        .post_subscriptions_with_language(
+            "name=Jane%20Doe&email=janedoe%40mail.com".into(),
+            "ja, es-AR;q=0.8, en;q=0.5",
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+
+    assert_eq!(saved.locale, "es");
+}
+
+#[actix_web::test]
+async fn subscribing_again_updates_the_locale() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = "name=Jane%20Doe&email=janedoe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions_with_language(body.into(), "en")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Test
+    test_app
+        .post_subscriptions_with_language(body.into(), "es")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+
+    assert_eq!(saved.locale, "es");
+}
+
+#[actix_web::test]
+async fn subscribe_prefers_the_requested_locale_over_the_accepted_language() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({"Subject": "Willkommen!"}),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app
+        .post_subscriptions_with_language(
+            "name=Jane%20Doe&email=janedoe%40mail.com&locale=de".into(),
+            "fr",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn subscribe_uses_the_default_locale_for_unsupported_languages() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({"Subject": "Welcome!"}),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app
+        .post_subscriptions_with_language(
+            "name=Jane%20Doe&email=janedoe%40mail.com&locale=ja".into(),
+            "ja",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn subscribe_propagates_the_trace_context_to_the_email_provider() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01"))
+        .body("name=jane%20doe&email=janedoe%40mail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request
+        .headers
+        .get(&"traceparent".into())
+        .expect("The trace context wasn't propagated.");
+    assert!(traceparent.as_str().contains(trace_id));
+}
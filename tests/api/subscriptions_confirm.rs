@@ -1,4 +1,6 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_sharing_database, spawn_app_with, TestApp};
+use newsletter::configuration::SigningKeySettings;
+use secrecy::Secret;
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
@@ -16,6 +18,27 @@ async fn confirmation_without_token_are_rejected_with_400() {
 
     // Assert
     assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+}
+
+#[actix_web::test]
+async fn confirmation_with_an_unknown_token_is_rejected_with_401() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
 }
 
 #[actix_web::test]
@@ -32,7 +55,7 @@ async fn the_link_returned_by_subscribe_resturns_a_200_if_called() {
 
     test_app.post_subscriptions(body.into()).await;
     let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = test_app.get_configuration_links(&email_request);
+    let confirmation_links = test_app.get_configuration_links(email_request);
 
     // Perform the request to the confirmation link.
     let response = reqwest::get(confirmation_links.html).await.unwrap();
@@ -55,7 +78,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
 
     test_app.post_subscriptions(body.into()).await;
     let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = test_app.get_configuration_links(&email_request);
+    let confirmation_links = test_app.get_configuration_links(email_request);
 
     // Test
     reqwest::get(confirmation_links.html)
@@ -74,3 +97,188 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "jane doe");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[actix_web::test]
+async fn confirmation_tokens_are_only_stored_as_hashes() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = "name=jane%20doe&email=janedoe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    test_app.post_subscriptions(body.into()).await;
+
+    // Checks
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_configuration_links(email_request);
+    let (_, token) = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens",)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+
+    assert_eq!(token.len(), 25);
+    assert_ne!(saved.subscription_token_hash, token);
+    assert_eq!(saved.subscription_token_hash.len(), 64);
+}
+
+async fn spawn_app_with_signed_tokens() -> TestApp {
+    spawn_app_with(|c| {
+        c.signed_tokens.enabled = true;
+        c.signed_tokens.keys = vec![SigningKeySettings {
+            id: "k1".into(),
+            secret: Secret::new("a-very-long-secret-to-sign-the-tokens".into()),
+        }];
+    })
+    .await
+}
+
+#[actix_web::test]
+async fn signed_confirmation_tokens_confirm_a_subscriber_without_storing_them() {
+    // Prepare
+    let test_app = spawn_app_with_signed_tokens().await;
+    let body = "name=jane%20doe&email=janedoe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_configuration_links(email_request);
+
+    // Test
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Checks
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    let stored_tokens = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens",)
+        .fetch_all(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved tokens.");
+
+    assert_eq!(saved.status, "confirmed");
+    assert!(stored_tokens.is_empty());
+}
+
+#[actix_web::test]
+async fn tampered_signed_confirmation_tokens_are_rejected_with_401() {
+    // Prepare
+    let test_app = spawn_app_with_signed_tokens().await;
+    let body = "name=jane%20doe&email=janedoe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let mut confirmation_link = test_app.get_configuration_links(email_request).html;
+    let (_, token) = confirmation_link
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap();
+    let tampered = token.replace(".confirm.", ".unsubscribe.");
+    confirmation_link
+        .query_pairs_mut()
+        .clear()
+        .append_pair("subscription_token", &tampered);
+
+    // Test
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Checks
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn signed_confirmation_tokens_of_erased_subscribers_are_rejected_with_401() {
+    // Prepare
+    let test_app = spawn_app_with_signed_tokens().await;
+    let body = "name=jane%20doe&email=janedoe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_configuration_links(email_request);
+    sqlx::query!("DELETE FROM subscriptions")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Test
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Checks
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn subscribers_created_with_signed_tokens_can_confirm_after_they_are_disabled() {
+    // Prepare
+    let signed_app = spawn_app_with_signed_tokens().await;
+    let body = "name=jane%20doe&email=janedoe%40mail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&signed_app.email_server)
+        .await;
+
+    signed_app.post_subscriptions(body.into()).await;
+
+    let test_app =
+        spawn_app_sharing_database(&signed_app, |c| c.signed_tokens.enabled = false).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_configuration_links(email_request);
+
+    // Test
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Checks
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
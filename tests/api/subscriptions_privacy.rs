@@ -0,0 +1,250 @@
+use crate::helpers::{spawn_app, PrivacyLinks, TestApp};
+use crate::newsletter::{create_confirmed_subscriber, newsletter_request_body};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Ask for the privacy links of `janedoe@mail.com`.
+async fn request_privacy_links(app: &TestApp) -> PrivacyLinks {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Privacy email")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_privacy_request(serde_json::json!({"email": "janedoe@Mail.com"}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_privacy_links(&email_request)
+}
+
+#[actix_web::test]
+async fn privacy_requests_for_unknown_emails_get_the_same_response() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app
+        .post_privacy_request(serde_json::json!({"email": "nobody@mail.com"}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "privacy_email_sent");
+}
+
+#[actix_web::test]
+async fn the_export_link_emails_the_data_of_the_subscriber() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let links = request_privacy_links(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = reqwest::get(links.export).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = email["TextBody"].as_str().unwrap();
+    let export: serde_json::Value = serde_json::from_str(&text[text.find('{').unwrap()..]).unwrap();
+
+    assert_eq!(export["subscription"]["email"], "janedoe@mail.com");
+    assert_eq!(export["subscription"]["name"], "Jane Doe");
+    assert_eq!(export["subscription"]["status"], "confirmed");
+    assert_eq!(
+        export["subscription_token_hashes"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[actix_web::test]
+async fn the_erasure_link_deletes_the_subscriber_and_anonymizes_deliveries() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let links = request_privacy_links(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_newsletters(newsletter_request_body())
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    // Test
+    let response = test_app.post_erasure(&links.erasure).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscriptions.is_empty());
+
+    let tokens = sqlx::query!("SELECT subscriber_id FROM subscription_tokens")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+
+    let delivery = sqlx::query!("SELECT subscriber_email, status FROM issue_deliveries")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(delivery.subscriber_email.starts_with("erased:"));
+    assert_eq!(delivery.status, "sent");
+}
+
+#[actix_web::test]
+async fn the_erasure_link_asks_for_a_confirmation_without_erasing() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let links = request_privacy_links(&test_app).await;
+
+    // Test
+    let response = reqwest::get(links.erasure).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"method="post""#));
+    assert!(page.contains(r#"name="privacy_token""#));
+
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriptions.len(), 1);
+}
+
+#[actix_web::test]
+async fn privacy_emails_are_sent_to_the_stored_email() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    // Test
+    request_privacy_links(&test_app).await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "janedoe@mail.com");
+}
+
+#[actix_web::test]
+async fn privacy_emails_are_not_sent_to_suppressed_emails() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app
+        .post_suppressions(serde_json::json!({"kind": "address", "value": "janedoe@mail.com"}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app
+        .post_privacy_request(serde_json::json!({"email": "janedoe@mail.com"}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn privacy_links_are_rejected_after_the_erasure() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    let links = request_privacy_links(&test_app).await;
+
+    test_app
+        .post_erasure(&links.erasure)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Test
+    let export = reqwest::get(links.export).await.unwrap();
+    let confirmation = reqwest::get(links.erasure.clone()).await.unwrap();
+    let erasure = test_app.post_erasure(&links.erasure).await;
+
+    // Assert
+    assert_eq!(export.status().as_u16(), 401);
+    assert_eq!(confirmation.status().as_u16(), 401);
+    assert_eq!(erasure.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn privacy_links_with_an_unknown_token_are_rejected() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = reqwest::get(format!(
+        "{}/subscriptions/privacy/export?privacy_token=unknown",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,154 @@
+//! tests/api/suppressions.rs
+
+use crate::helpers::spawn_app;
+use crate::newsletter::create_confirmed_subscriber;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn confirmation_emails_are_not_sent_to_suppressed_addresses() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = "name=Jane%20Doe&email=janedoe%40mail.com";
+
+    test_app
+        .post_suppressions(serde_json::json!({
+            "kind": "address",
+            "value": "janedoe@Mail.com",
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // Mock verifies on Drop that we haven't sent the confirmation email
+}
+
+#[actix_web::test]
+async fn newsletters_are_not_delivered_to_suppressed_domains() {
+    // Prepare
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    test_app
+        .post_suppressions(serde_json::json!({
+            "kind": "domain",
+            "value": "mail.com",
+            "reason": "Competitor",
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+
+    let response = test_app.post_newsletters(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    test_app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_web::test]
+async fn removed_suppressions_are_mailed_again() {
+    // Prepare
+    let test_app = spawn_app().await;
+    let body = "name=Jane%20Doe&email=janedoe%40mail.com";
+
+    test_app
+        .post_suppressions(serde_json::json!({
+            "kind": "domain",
+            "value": "mail.com",
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Test
+    let response = test_app.delete_suppression("mail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    test_app.post_subscriptions(body.into()).await;
+
+    // Mock verifies on Drop that we have sent the confirmation email
+}
+
+#[actix_web::test]
+async fn removing_an_unknown_suppression_returns_404() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    // Test
+    let response = test_app.delete_suppression("mail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn suppressions_returns_400_for_invalid_data() {
+    // Prepare
+    let test_app = spawn_app().await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({"kind": "address", "value": "not-an-email"}),
+            "invalid address",
+        ),
+        (
+            serde_json::json!({"kind": "domain", "value": ""}),
+            "empty domain",
+        ),
+        (
+            serde_json::json!({"kind": "domain", "value": "jane@mail.com"}),
+            "address given as a domain",
+        ),
+        (
+            serde_json::json!({"kind": "user", "value": "mail.com"}),
+            "unknown kind",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = test_app.post_suppressions(invalid_body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message,
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use newsletter::configuration::TlsSettings;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+/// Copy a certificate of the fixtures to the given paths.
+fn copy_certificate(name: &str, certificate_path: &Path, private_key_path: &Path) {
+    let fixture = |extension: &str| PathBuf::from(FIXTURES).join(format!("{name}.{extension}"));
+    std::fs::copy(fixture("crt"), certificate_path).unwrap();
+    std::fs::copy(fixture("key"), private_key_path).unwrap();
+}
+
+/// Spawn the application serving HTTPS with a copy of the `first` certificate.
+async fn spawn_https_app(redirect_port: Option<u16>) -> (TestApp, TlsSettings) {
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    std::fs::create_dir(&directory).unwrap();
+    let tls = TlsSettings {
+        certificate_path: directory.join("server.crt").to_str().unwrap().into(),
+        private_key_path: directory.join("server.key").to_str().unwrap().into(),
+        reload_interval_seconds: 1,
+        redirect_port,
+    };
+    copy_certificate(
+        "first",
+        Path::new(&tls.certificate_path),
+        Path::new(&tls.private_key_path),
+    );
+
+    let settings = tls.clone();
+    let test_app = spawn_app_with(|c| c.application.tls = Some(settings)).await;
+
+    (test_app, tls)
+}
+
+/// Client that trusts both certificates of the fixtures.
+fn https_client() -> reqwest::Client {
+    let mut builder = reqwest::Client::builder()
+        .tls_info(true)
+        .redirect(reqwest::redirect::Policy::none());
+    for name in ["first", "second"] {
+        let pem = std::fs::read(PathBuf::from(FIXTURES).join(format!("{name}.crt"))).unwrap();
+        builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem).unwrap());
+    }
+
+    builder.build().unwrap()
+}
+
+/// DER encoding of a certificate of the fixtures.
+fn certificate_der(name: &str) -> Vec<u8> {
+    let pem = std::fs::read(PathBuf::from(FIXTURES).join(format!("{name}.crt"))).unwrap();
+    rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0)
+}
+
+/// DER encoding of the certificate served by the application.
+async fn served_certificate(client: &reqwest::Client, port: u16) -> Vec<u8> {
+    let response = client
+        .get(format!("https://localhost:{port}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .unwrap()
+        .to_vec()
+}
+
+#[actix_web::test]
+async fn the_application_serves_https_when_tls_is_configured() {
+    // Prepare
+    let (test_app, _) = spawn_https_app(None).await;
+
+    // Test
+    let certificate = served_certificate(&https_client(), test_app.port).await;
+
+    // Assert
+    assert_eq!(certificate, certificate_der("first"));
+    assert!(test_app.redirect_port.is_none());
+}
+
+#[actix_web::test]
+async fn renewed_certificates_are_served_without_a_restart() {
+    // Prepare
+    let (test_app, tls) = spawn_https_app(None).await;
+    let client = https_client();
+    assert_eq!(
+        served_certificate(&client, test_app.port).await,
+        certificate_der("first")
+    );
+
+    // Test
+    copy_certificate(
+        "second",
+        Path::new(&tls.certificate_path),
+        Path::new(&tls.private_key_path),
+    );
+
+    // Assert: the new certificate is served once the files are checked again.
+    let mut certificate = Vec::new();
+    for _ in 0..30 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // A new client, so the TLS session isn't resumed.
+        certificate = served_certificate(&https_client(), test_app.port).await;
+        if certificate == certificate_der("second") {
+            break;
+        }
+    }
+    assert_eq!(certificate, certificate_der("second"));
+}
+
+#[actix_web::test]
+async fn plain_http_requests_are_redirected_to_https() {
+    // Prepare
+    let (test_app, _) = spawn_https_app(Some(0)).await;
+    let redirect_port = test_app.redirect_port.unwrap();
+
+    // Test
+    let response = https_client()
+        .get(format!(
+            "http://localhost:{redirect_port}/subscribe/form?locale=es"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 308);
+    assert_eq!(
+        response.headers()["Location"],
+        format!(
+            "https://localhost:{}/subscribe/form?locale=es",
+            test_app.port
+        )
+        .as_str()
+    );
+}